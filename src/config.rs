@@ -1,6 +1,7 @@
+use crate::rpc::Throttled;
 use ethers::{
-    core::rand::{seq::SliceRandom, thread_rng},
     providers::{Http, Provider, RetryClient},
+    types::BlockNumber,
 };
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{collections::HashMap, fs, sync::Arc};
@@ -11,8 +12,7 @@ lazy_static! {
 }
 
 pub type ChainId = u64;
-pub type HttpProvider = Arc<Provider<RetryClient<Http>>>;
-pub type HttpProviders = Vec<HttpProvider>;
+pub type HttpProvider = Arc<Provider<RetryClient<Throttled<Http>>>>;
 
 pub fn read_yaml<T: DeserializeOwned>(path: &str) -> Result<T, serde_yaml::Error> {
     let content = fs::read_to_string(path).unwrap();
@@ -24,15 +24,129 @@ pub fn read_yaml<T: DeserializeOwned>(path: &str) -> Result<T, serde_yaml::Error
 pub struct ChainConfig {
     pub name: String,
     pub wss: String,
-    pub https: Vec<String>,
+    pub https: Vec<HttpConfig>,
+    /// Protocols (`p`) indexed on this chain, any protocol if omitted.
+    #[serde(default)]
+    pub protocols: Option<Vec<String>>,
+    /// Skip reverted transactions by their receipts, can be turned off on chains
+    /// where reverted self-transfers cannot happen.
+    #[serde(default = "default_check_receipts")]
+    pub check_receipts: bool,
+    /// Number of recent blocks which can be rolled back on a reorg, at least 1.
+    #[serde(default = "default_reorg_depth")]
+    pub reorg_depth: u64,
+    /// Blocks are only indexed once they are `confirmations` blocks behind the head.
+    #[serde(default)]
+    pub confirmations: u64,
+    /// Block tag followed as the head, `safe` or `finalized` only index settled blocks.
+    #[serde(default)]
+    pub head: HeadTag,
+    /// Number of blocks fetched ahead concurrently, they are still applied in order.
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+    /// Number of blocks fetched in a single JSON-RPC batch request.
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
 }
 
-pub trait Random<T> {
-    fn random(&self) -> Result<T, anyhow::Error>;
+/// An `https` provider, given either as a plain url or as a url with its limits.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(from = "HttpConfigEntry")]
+pub struct HttpConfig {
+    pub url: String,
+    /// Requests per second, unlimited if omitted.
+    pub rps: Option<u32>,
+    /// Requests in flight, unlimited if omitted.
+    pub max_concurrency: Option<usize>,
+    /// Retries of rate limited or timed out requests.
+    pub retries: u32,
+    /// Initial backoff between retries in milliseconds, doubled on every retry.
+    pub backoff_ms: u64,
 }
 
-impl Random<HttpProvider> for HttpProviders {
-    fn random(&self) -> Result<HttpProvider, anyhow::Error> {
-        Ok(self.choose(&mut thread_rng()).unwrap().to_owned())
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum HttpConfigEntry {
+    Url(String),
+    Config {
+        url: String,
+        #[serde(default)]
+        rps: Option<u32>,
+        #[serde(default)]
+        max_concurrency: Option<usize>,
+        #[serde(default = "default_retries")]
+        retries: u32,
+        #[serde(default = "default_backoff_ms")]
+        backoff_ms: u64,
+    },
+}
+
+impl From<HttpConfigEntry> for HttpConfig {
+    fn from(entry: HttpConfigEntry) -> Self {
+        match entry {
+            HttpConfigEntry::Url(url) => HttpConfig {
+                url,
+                rps: None,
+                max_concurrency: None,
+                retries: default_retries(),
+                backoff_ms: default_backoff_ms(),
+            },
+            HttpConfigEntry::Config {
+                url,
+                rps,
+                max_concurrency,
+                retries,
+                backoff_ms,
+            } => HttpConfig {
+                url,
+                rps,
+                max_concurrency,
+                retries,
+                backoff_ms,
+            },
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HeadTag {
+    #[default]
+    Latest,
+    Safe,
+    Finalized,
+}
+
+impl From<HeadTag> for BlockNumber {
+    fn from(tag: HeadTag) -> Self {
+        match tag {
+            HeadTag::Latest => BlockNumber::Latest,
+            HeadTag::Safe => BlockNumber::Safe,
+            HeadTag::Finalized => BlockNumber::Finalized,
+        }
     }
 }
+
+fn default_check_receipts() -> bool {
+    true
+}
+
+fn default_reorg_depth() -> u64 {
+    64
+}
+
+fn default_concurrency() -> usize {
+    1
+}
+
+fn default_batch_size() -> usize {
+    1
+}
+
+fn default_retries() -> u32 {
+    5
+}
+
+fn default_backoff_ms() -> u64 {
+    10
+}
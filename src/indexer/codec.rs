@@ -0,0 +1,615 @@
+use super::{
+    journal::UndoEntry, Balance, DBInscription, Ethscription, EthscriptionTransfer, IndexedRecord,
+    Tick,
+};
+use anyhow::anyhow;
+use bigdecimal::{num_bigint::BigInt, BigDecimal};
+use ethers::utils::hex;
+
+// JSON values start with `{` or `[`, so a leading zero byte marks the binary encoding
+const MARKER: u8 = 0;
+
+/// A value stored in the binary encoding, headed by the marker and the schema version
+/// of the value. `read` is given the version the value was written with, so a struct
+/// can evolve without rewriting the values written before.
+pub trait Codec: Sized {
+    const VERSION: u8;
+
+    fn write(&self, w: &mut Writer);
+    fn read(r: &mut Reader, version: u8) -> Result<Self, anyhow::Error>;
+
+    fn encode(&self) -> Vec<u8> {
+        let mut w = Writer(vec![MARKER, Self::VERSION]);
+        self.write(&mut w);
+        w.0
+    }
+
+    fn decode(bs: &[u8]) -> Result<Self, anyhow::Error> {
+        if bs.len() < 2 || bs[0] != MARKER {
+            return Err(anyhow!("Value is not in the binary encoding"));
+        }
+        let version = bs[1];
+        if version == 0 || version > Self::VERSION {
+            return Err(anyhow!("Unsupported schema version {}", version));
+        }
+        let mut r = Reader { bs, pos: 2 };
+        let value = Self::read(&mut r, version)?;
+        if r.pos != bs.len() {
+            return Err(anyhow!("Trailing bytes after the value"));
+        }
+        Ok(value)
+    }
+}
+
+pub fn is_encoded(bs: &[u8]) -> bool {
+    bs.first().is_some_and(|x| *x == MARKER)
+}
+
+pub struct Writer(Vec<u8>);
+
+impl Writer {
+    pub fn u8(&mut self, v: u8) {
+        self.0.push(v);
+    }
+    /// LEB128 varint.
+    pub fn u64(&mut self, mut v: u64) {
+        while v >= 0x80 {
+            self.0.push(v as u8 | 0x80);
+            v >>= 7;
+        }
+        self.0.push(v as u8);
+    }
+    pub fn i64(&mut self, v: i64) {
+        self.u64(((v << 1) ^ (v >> 63)) as u64);
+    }
+    pub fn bool(&mut self, v: bool) {
+        self.u8(v as u8);
+    }
+    pub fn bytes(&mut self, v: &[u8]) {
+        self.u64(v.len() as u64);
+        self.0.extend_from_slice(v);
+    }
+    pub fn str(&mut self, v: &str) {
+        self.bytes(v.as_bytes());
+    }
+    pub fn option<T>(&mut self, v: Option<T>, f: impl FnOnce(&mut Self, T)) {
+        self.bool(v.is_some());
+        if let Some(v) = v {
+            f(self, v);
+        }
+    }
+    /// A `0x` prefixed lowercase hex string such as a hash or an address, as raw bytes.
+    /// Any other string is kept as is.
+    pub fn hex(&mut self, v: &str) {
+        match v.strip_prefix("0x").and_then(|x| hex::decode(x).ok()) {
+            Some(bs) if format!("0x{}", hex::encode(&bs)).eq(v) => {
+                self.u8(1);
+                self.bytes(&bs);
+            }
+            _ => {
+                self.u8(0);
+                self.str(v);
+            }
+        }
+    }
+    /// A decimal amount as its digits and scale, the string is kept as is
+    /// unless it is the plain form of the decimal, never an exponent.
+    pub fn amount(&mut self, v: &str) {
+        match v.parse::<BigDecimal>() {
+            Ok(x) if x.to_plain_string().eq(v) => {
+                let (digits, scale) = x.as_bigint_and_exponent();
+                self.u8(1);
+                self.bytes(&digits.to_signed_bytes_le());
+                self.i64(scale);
+            }
+            _ => {
+                self.u8(0);
+                self.str(v);
+            }
+        }
+    }
+}
+
+pub struct Reader<'a> {
+    bs: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn u8(&mut self) -> Result<u8, anyhow::Error> {
+        let v = *self
+            .bs
+            .get(self.pos)
+            .ok_or(anyhow!("Unexpected end of value"))?;
+        self.pos += 1;
+        Ok(v)
+    }
+    pub fn u64(&mut self) -> Result<u64, anyhow::Error> {
+        let mut v = 0u64;
+        for shift in (0..64).step_by(7) {
+            let b = self.u8()?;
+            v |= ((b & 0x7f) as u64) << shift;
+            if b < 0x80 {
+                return Ok(v);
+            }
+        }
+        Err(anyhow!("Varint overflow"))
+    }
+    pub fn u32(&mut self) -> Result<u32, anyhow::Error> {
+        Ok(u32::try_from(self.u64()?)?)
+    }
+    pub fn i64(&mut self) -> Result<i64, anyhow::Error> {
+        let v = self.u64()?;
+        Ok((v >> 1) as i64 ^ -((v & 1) as i64))
+    }
+    pub fn bool(&mut self) -> Result<bool, anyhow::Error> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            x => Err(anyhow!("Invalid bool {}", x)),
+        }
+    }
+    pub fn bytes(&mut self) -> Result<&'a [u8], anyhow::Error> {
+        let len = self.u64()? as usize;
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|x| *x <= self.bs.len())
+            .ok_or(anyhow!("Unexpected end of value"))?;
+        let v = &self.bs[self.pos..end];
+        self.pos = end;
+        Ok(v)
+    }
+    pub fn string(&mut self) -> Result<String, anyhow::Error> {
+        Ok(String::from_utf8(self.bytes()?.to_vec())?)
+    }
+    pub fn option<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T, anyhow::Error>,
+    ) -> Result<Option<T>, anyhow::Error> {
+        if self.bool()? {
+            return Ok(Some(f(self)?));
+        }
+        Ok(None)
+    }
+    pub fn hex(&mut self) -> Result<String, anyhow::Error> {
+        match self.u8()? {
+            0 => self.string(),
+            1 => Ok(format!("0x{}", hex::encode(self.bytes()?))),
+            x => Err(anyhow!("Invalid hex tag {}", x)),
+        }
+    }
+    pub fn amount(&mut self) -> Result<String, anyhow::Error> {
+        match self.u8()? {
+            0 => self.string(),
+            1 => {
+                let digits = BigInt::from_signed_bytes_le(self.bytes()?);
+                let scale = self.i64()?;
+                // amounts written with an exponent by earlier releases, e.g. `1E-8`, read back plain
+                Ok(BigDecimal::new(digits, scale).to_plain_string())
+            }
+            x => Err(anyhow!("Invalid amount tag {}", x)),
+        }
+    }
+}
+
+impl Codec for IndexedRecord {
+    const VERSION: u8 = 1;
+
+    fn write(&self, w: &mut Writer) {
+        w.u64(self.chain_id);
+        w.u64(self.indexed_block);
+        w.i64(self.indexed_txi);
+    }
+
+    fn read(r: &mut Reader, _version: u8) -> Result<Self, anyhow::Error> {
+        Ok(IndexedRecord {
+            chain_id: r.u64()?,
+            indexed_block: r.u64()?,
+            indexed_txi: r.i64()?,
+        })
+    }
+}
+
+impl Codec for Tick {
+    const VERSION: u8 = 1;
+
+    fn write(&self, w: &mut Writer) {
+        w.hex(&self.id);
+        w.u64(self.chain_id);
+        w.str(&self.chain);
+        w.str(&self.p);
+        w.str(&self.op);
+        w.str(&self.tick);
+        w.option(self.max.as_deref(), Writer::amount);
+        w.option(self.lim.as_deref(), Writer::amount);
+        w.u64(self.dec as u64);
+        w.u64(self.start_block);
+        w.option(self.end_block, Writer::u64);
+        w.amount(&self.minted);
+        w.bool(self.mintable);
+        w.amount(&self.holders);
+        w.u64(self.timestamp);
+        w.hex(&self.deployer);
+    }
+
+    fn read(r: &mut Reader, _version: u8) -> Result<Self, anyhow::Error> {
+        Ok(Tick {
+            id: r.hex()?,
+            chain_id: r.u64()?,
+            chain: r.string()?,
+            p: r.string()?,
+            op: r.string()?,
+            tick: r.string()?,
+            max: r.option(Reader::amount)?,
+            lim: r.option(Reader::amount)?,
+            dec: r.u32()?,
+            start_block: r.u64()?,
+            end_block: r.option(Reader::u64)?,
+            minted: r.amount()?,
+            mintable: r.bool()?,
+            holders: r.amount()?,
+            timestamp: r.u64()?,
+            deployer: r.hex()?,
+        })
+    }
+}
+
+impl Codec for DBInscription {
+    const VERSION: u8 = 2;
+
+    fn write(&self, w: &mut Writer) {
+        w.hex(&self.id);
+        w.u64(self.chain_id);
+        w.str(&self.chain);
+        w.str(&self.p);
+        w.str(&self.op);
+        w.str(&self.tick);
+        w.option(self.max.as_deref(), Writer::amount);
+        w.option(self.lim.as_deref(), Writer::amount);
+        w.option(self.amt.as_deref(), Writer::amount);
+        w.u64(self.block);
+        w.hex(&self.owner);
+        w.u64(self.timestamp);
+        w.option(self.txi, Writer::u64);
+    }
+
+    fn read(r: &mut Reader, version: u8) -> Result<Self, anyhow::Error> {
+        Ok(DBInscription {
+            id: r.hex()?,
+            chain_id: r.u64()?,
+            chain: r.string()?,
+            p: r.string()?,
+            op: r.string()?,
+            tick: r.string()?,
+            max: r.option(Reader::amount)?,
+            lim: r.option(Reader::amount)?,
+            amt: r.option(Reader::amount)?,
+            block: r.u64()?,
+            owner: r.hex()?,
+            timestamp: r.u64()?,
+            // version 1 didn't keep the transaction index
+            txi: match version {
+                1 => None,
+                _ => r.option(Reader::u64)?,
+            },
+        })
+    }
+}
+
+impl Codec for Balance {
+    const VERSION: u8 = 1;
+
+    fn write(&self, w: &mut Writer) {
+        w.u64(self.chain_id);
+        w.str(&self.p);
+        w.str(&self.tick);
+        w.hex(&self.holder);
+        w.amount(&self.amount);
+    }
+
+    fn read(r: &mut Reader, _version: u8) -> Result<Self, anyhow::Error> {
+        Ok(Balance {
+            chain_id: r.u64()?,
+            p: r.string()?,
+            tick: r.string()?,
+            holder: r.hex()?,
+            amount: r.amount()?,
+        })
+    }
+}
+
+impl Codec for Ethscription {
+    const VERSION: u8 = 1;
+
+    fn write(&self, w: &mut Writer) {
+        w.hex(&self.id);
+        w.u64(self.chain_id);
+        w.str(&self.chain);
+        w.hex(&self.content_sha);
+        w.str(&self.mimetype);
+        w.hex(&self.creator);
+        w.hex(&self.initial_owner);
+        w.hex(&self.current_owner);
+        w.option(self.previous_owner.as_deref(), Writer::hex);
+        w.u64(self.block);
+        w.u64(self.txi);
+        w.u64(self.timestamp);
+    }
+
+    fn read(r: &mut Reader, _version: u8) -> Result<Self, anyhow::Error> {
+        Ok(Ethscription {
+            id: r.hex()?,
+            chain_id: r.u64()?,
+            chain: r.string()?,
+            content_sha: r.hex()?,
+            mimetype: r.string()?,
+            creator: r.hex()?,
+            initial_owner: r.hex()?,
+            current_owner: r.hex()?,
+            previous_owner: r.option(Reader::hex)?,
+            block: r.u64()?,
+            txi: r.u64()?,
+            timestamp: r.u64()?,
+        })
+    }
+}
+
+impl Codec for EthscriptionTransfer {
+    const VERSION: u8 = 1;
+
+    fn write(&self, w: &mut Writer) {
+        w.hex(&self.id);
+        w.hex(&self.from);
+        w.hex(&self.to);
+        w.hex(&self.hash);
+        w.u64(self.block);
+        w.u64(self.txi);
+        w.option(self.log_index, Writer::u64);
+        w.u64(self.timestamp);
+    }
+
+    fn read(r: &mut Reader, _version: u8) -> Result<Self, anyhow::Error> {
+        Ok(EthscriptionTransfer {
+            id: r.hex()?,
+            from: r.hex()?,
+            to: r.hex()?,
+            hash: r.hex()?,
+            block: r.u64()?,
+            txi: r.u64()?,
+            log_index: r.option(Reader::u64)?,
+            timestamp: r.u64()?,
+        })
+    }
+}
+
+/// The undo journal of a block, the previous values are kept in whatever encoding they had.
+impl Codec for Vec<UndoEntry> {
+    const VERSION: u8 = 1;
+
+    fn write(&self, w: &mut Writer) {
+        w.u64(self.len() as u64);
+        for entry in self.iter() {
+            w.str(&entry.key);
+            w.option(entry.value.as_deref(), Writer::bytes);
+        }
+    }
+
+    fn read(r: &mut Reader, _version: u8) -> Result<Self, anyhow::Error> {
+        let len = r.u64()?;
+        let mut entries = vec![];
+        for _ in 0..len {
+            entries.push(UndoEntry {
+                key: r.string()?,
+                value: r.option(|r| Ok(r.bytes()?.to_vec()))?,
+            });
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fmt::Debug;
+
+    const HASH: &str = "0x00112233445566778899aabbccddeeff00112233445566778899aabbccddeeff";
+    const ADDRESS: &str = "0x00112233445566778899aabbccddeeff00112233";
+
+    fn round_trip<T: Codec + Debug>(value: T) {
+        let decoded = T::decode(&value.encode()).unwrap();
+        assert_eq!(format!("{:?}", decoded), format!("{:?}", value));
+    }
+
+    fn tick() -> Tick {
+        Tick {
+            id: HASH.to_string(),
+            chain_id: 56,
+            chain: "BNB".to_string(),
+            p: "bsc-20".to_string(),
+            op: "deploy".to_string(),
+            tick: "bnbs".to_string(),
+            max: Some("21000000".to_string()),
+            lim: Some("0010".to_string()),
+            dec: 8,
+            start_block: 35_000_000,
+            end_block: None,
+            minted: "1.50".to_string(),
+            mintable: true,
+            holders: "7".to_string(),
+            timestamp: 1_700_000_000,
+            deployer: ADDRESS.to_string(),
+        }
+    }
+
+    fn mint(txi: Option<u64>) -> DBInscription {
+        DBInscription {
+            id: HASH.to_string(),
+            chain_id: 56,
+            chain: "BNB".to_string(),
+            p: "bsc-20".to_string(),
+            op: "mint".to_string(),
+            tick: "bnbs".to_string(),
+            max: None,
+            lim: None,
+            amt: Some("1000".to_string()),
+            block: 35_000_001,
+            owner: ADDRESS.to_string(),
+            timestamp: 1_700_000_012,
+            txi,
+        }
+    }
+
+    #[test]
+    fn round_trips_every_value() {
+        round_trip(IndexedRecord {
+            chain_id: 1,
+            indexed_block: 19_000_000,
+            indexed_txi: -1,
+        });
+        round_trip(tick());
+        round_trip(Tick {
+            end_block: Some(35_000_100),
+            mintable: false,
+            max: None,
+            ..tick()
+        });
+        round_trip(mint(Some(3)));
+        round_trip(mint(None));
+        round_trip(Balance {
+            chain_id: 56,
+            p: "bsc-20".to_string(),
+            tick: "bnbs".to_string(),
+            holder: ADDRESS.to_string(),
+            amount: "0.00000001".to_string(),
+        });
+        round_trip(Ethscription {
+            id: HASH.to_string(),
+            chain_id: 1,
+            chain: "Ethereum".to_string(),
+            content_sha: HASH.to_string(),
+            mimetype: "text/plain".to_string(),
+            creator: ADDRESS.to_string(),
+            initial_owner: ADDRESS.to_string(),
+            current_owner: ADDRESS.to_string(),
+            previous_owner: Some(ADDRESS.to_string()),
+            block: 17_000_000,
+            txi: 42,
+            timestamp: 1_680_000_000,
+        });
+        round_trip(EthscriptionTransfer {
+            id: "not-a-hash".to_string(),
+            from: ADDRESS.to_string(),
+            to: ADDRESS.to_string(),
+            hash: HASH.to_string(),
+            block: 17_000_001,
+            txi: 0,
+            log_index: Some(3),
+            timestamp: 1_680_000_012,
+        });
+        round_trip(vec![
+            UndoEntry {
+                key: "deploy#1#text/plain#brc-20#ordi".to_string(),
+                value: Some(tick().encode()),
+            },
+            UndoEntry {
+                key: "balance#1#text/plain#brc-20#ordi#0x01".to_string(),
+                value: None,
+            },
+        ]);
+    }
+
+    #[test]
+    fn reads_mints_written_without_their_txi() {
+        // a version 1 mint is a version 2 one without the trailing txi
+        let mut bs = mint(None).encode();
+        bs[1] = 1;
+        bs.pop();
+        let decoded = DBInscription::decode(&bs).unwrap();
+        assert_eq!(format!("{:?}", decoded), format!("{:?}", mint(None)));
+        // and a version 1 value doesn't read past its end
+        let mut bs = mint(Some(3)).encode();
+        bs[1] = 1;
+        assert!(DBInscription::decode(&bs).is_err());
+    }
+
+    #[test]
+    fn keeps_amounts_which_are_not_plain_as_strings() {
+        for (amount, tag) in [
+            ("1000", 1),
+            ("1.50", 1),
+            ("0.00000001", 1),
+            ("0010", 0),
+            ("1e3", 0),
+            ("+1", 0),
+            ("", 0),
+        ] {
+            let mut w = Writer(vec![]);
+            w.amount(amount);
+            assert_eq!(w.0[0], tag, "tag of {:?}", amount);
+            let mut r = Reader { bs: &w.0, pos: 0 };
+            assert_eq!(r.amount().unwrap(), amount);
+            assert_eq!(r.pos, w.0.len());
+        }
+    }
+
+    #[test]
+    fn keeps_hex_which_is_not_canonical_as_strings() {
+        for (hex, tag) in [
+            (HASH, 1),
+            (ADDRESS, 1),
+            ("0x", 1),
+            ("not-a-hash", 0),
+            ("0xABCD", 0),
+            ("0x123", 0),
+            ("00ff", 0),
+        ] {
+            let mut w = Writer(vec![]);
+            w.hex(hex);
+            assert_eq!(w.0[0], tag, "tag of {:?}", hex);
+            let mut r = Reader { bs: &w.0, pos: 0 };
+            assert_eq!(r.hex().unwrap(), hex);
+        }
+    }
+
+    #[test]
+    fn round_trips_integer_bounds() {
+        let mut w = Writer(vec![]);
+        w.u64(u64::MAX);
+        w.i64(i64::MIN);
+        w.i64(i64::MAX);
+        w.i64(-1);
+        let mut r = Reader { bs: &w.0, pos: 0 };
+        assert_eq!(r.u64().unwrap(), u64::MAX);
+        assert_eq!(r.i64().unwrap(), i64::MIN);
+        assert_eq!(r.i64().unwrap(), i64::MAX);
+        assert_eq!(r.i64().unwrap(), -1);
+    }
+
+    #[test]
+    fn rejects_malformed_values() {
+        let encoded = tick().encode();
+        for end in 0..encoded.len() {
+            assert!(
+                Tick::decode(&encoded[..end]).is_err(),
+                "truncated at {}",
+                end
+            );
+        }
+        let mut trailing = encoded.to_owned();
+        trailing.push(0);
+        assert!(Tick::decode(&trailing).is_err());
+        let mut newer = encoded.to_owned();
+        newer[1] = Tick::VERSION + 1;
+        assert!(Tick::decode(&newer).is_err());
+        newer[1] = 0;
+        assert!(Tick::decode(&newer).is_err());
+        assert!(Tick::decode(br#"{"id":"0x"}"#).is_err());
+        // a varint longer than 64 bits
+        let mut r = Reader {
+            bs: &[0xff; 11],
+            pos: 0,
+        };
+        assert!(r.u64().is_err());
+    }
+}
@@ -0,0 +1,189 @@
+use super::migration;
+use rocksdb::{
+    BlockBasedOptions, ColumnFamily, ColumnFamilyDescriptor, DBCompressionType, Error, Options,
+    SliceTransform, Transaction, TransactionDB, TransactionDBOptions, WriteOptions,
+    DEFAULT_COLUMN_FAMILY_NAME,
+};
+
+pub const CF_TICKS: &'static str = "ticks";
+pub const CF_MINTS: &'static str = "mints";
+pub const CF_MINT_INDEXES: &'static str = "mintindexes";
+pub const CF_BALANCES: &'static str = "balances";
+pub const CF_ETHSCRIPTIONS: &'static str = "ethscriptions";
+pub const CF_CURSORS: &'static str = "cursors";
+pub const CF_BLOCK_HASHES: &'static str = "blockhashes";
+pub const CF_UNDO: &'static str = "undo";
+
+pub const COLUMNS: [&str; 8] = [
+    CF_TICKS,
+    CF_MINTS,
+    CF_MINT_INDEXES,
+    CF_BALANCES,
+    CF_ETHSCRIPTIONS,
+    CF_CURSORS,
+    CF_BLOCK_HASHES,
+    CF_UNDO,
+];
+
+/// The column family of a key, by the entity prefix of the keys built in `keys.rs`.
+pub fn column_of(key: &[u8]) -> &'static str {
+    let entity = key.split(|x| *x == b'#').next().unwrap_or_default();
+    match entity {
+        b"deploy" => CF_TICKS,
+        b"mint" => CF_MINTS,
+        b"minttx" | b"mintblock" | b"mintowner" => CF_MINT_INDEXES,
+        b"balance" => CF_BALANCES,
+        b"ethscription" | b"content" | b"owner" | b"transfer" => CF_ETHSCRIPTIONS,
+        b"indexed" => CF_CURSORS,
+        b"blockhash" => CF_BLOCK_HASHES,
+        b"undo" | b"undoseq" => CF_UNDO,
+        _ => DEFAULT_COLUMN_FAMILY_NAME,
+    }
+}
+
+/// The leading `N` segments of a key including their trailing `#`,
+/// e.g. `mint#chain#type#p#tick#` so that the mints of a tick share a prefix.
+fn segments<const N: usize>(key: &[u8]) -> &[u8] {
+    let end = key
+        .iter()
+        .enumerate()
+        .filter(|(_, x)| **x == b'#')
+        .nth(N - 1)
+        .map(|(i, _)| i + 1)
+        .unwrap_or(key.len());
+    &key[..end]
+}
+
+fn has_segments<const N: usize>(key: &[u8]) -> bool {
+    key.iter().filter(|x| **x == b'#').count() >= N
+}
+
+fn column_options(name: &str) -> Options {
+    let mut opts = Options::default();
+    // a handful of records, only looked up
+    if name == CF_CURSORS {
+        opts.optimize_for_point_lookup(8);
+        return opts;
+    }
+    let mut table = BlockBasedOptions::default();
+    table.set_bloom_filter(10.0, false);
+    match name {
+        // scanned by tick, the extractor is renamed along with its prefix so that the filters
+        // of files written with the prefix before ledger keys had a type segment are ignored
+        CF_MINTS | CF_BALANCES => {
+            opts.set_prefix_extractor(SliceTransform::create(
+                "typed_tick",
+                segments::<5>,
+                Some(has_segments::<5>),
+            ));
+            opts.set_memtable_prefix_bloom_ratio(0.1);
+            table.set_whole_key_filtering(true);
+            opts.set_compression_type(DBCompressionType::Lz4);
+        }
+        // scanned by block when rolling back or pruning
+        CF_UNDO => {
+            opts.set_prefix_extractor(SliceTransform::create(
+                "block",
+                segments::<4>,
+                Some(has_segments::<4>),
+            ));
+            opts.set_memtable_prefix_bloom_ratio(0.1);
+            opts.set_compression_type(DBCompressionType::Lz4);
+        }
+        // mint indexes are scanned by block or by owner, prefixes of different lengths
+        CF_TICKS | CF_MINT_INDEXES | CF_BLOCK_HASHES | CF_ETHSCRIPTIONS => {
+            opts.set_compression_type(DBCompressionType::Lz4);
+        }
+        _ => {}
+    }
+    opts.set_block_based_table_factory(&table);
+    opts
+}
+
+/// Opens the database with every column family and migrates it to the latest schema.
+pub fn open(path: &str) -> Result<TransactionDB, anyhow::Error> {
+    let mut opts = Options::default();
+    opts.create_if_missing(true);
+    opts.create_missing_column_families(true);
+    let mut descriptors = vec![ColumnFamilyDescriptor::new(
+        DEFAULT_COLUMN_FAMILY_NAME,
+        Options::default(),
+    )];
+    descriptors.extend(
+        COLUMNS
+            .iter()
+            .map(|x| ColumnFamilyDescriptor::new(*x, column_options(x))),
+    );
+    let txn_opts = TransactionDBOptions::default();
+    let db = TransactionDB::open_cf_descriptors(&opts, &txn_opts, path, descriptors)?;
+    migration::migrate(&db)?;
+    Ok(db)
+}
+
+// never written, only deleted to sync the write-ahead log
+const SYNC_KEY: &'static str = "sync";
+
+/// Syncs the write-ahead log, so that every transaction committed before survives a crash of
+/// the host. `TransactionDB` has no `flush_wal`, a synced write syncs the log up to itself and
+/// deleting a key which doesn't exist leaves the records untouched.
+pub fn sync_wal(db: &TransactionDB) -> Result<(), Error> {
+    let mut opts = WriteOptions::default();
+    opts.set_sync(true);
+    db.delete_opt(SYNC_KEY.as_bytes(), &opts)
+}
+
+pub trait Columns {
+    /// The column family holding `key`.
+    fn column(&self, key: &[u8]) -> &ColumnFamily;
+}
+
+impl Columns for TransactionDB {
+    fn column(&self, key: &[u8]) -> &ColumnFamily {
+        self.cf_handle(column_of(key)).unwrap()
+    }
+}
+
+/// A transaction whose reads and writes go to the column family of each key.
+pub struct Txn<'a> {
+    db: &'a TransactionDB,
+    txn: Transaction<'a, TransactionDB>,
+}
+
+impl<'a> Txn<'a> {
+    pub fn new(db: &'a TransactionDB) -> Self {
+        Txn {
+            db,
+            txn: db.transaction(),
+        }
+    }
+
+    pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Vec<u8>>, Error> {
+        self.txn.get_cf(self.db.column(key.as_ref()), key)
+    }
+
+    pub fn put<K: AsRef<[u8]>, V: AsRef<[u8]>>(&self, key: K, value: V) -> Result<(), Error> {
+        self.txn.put_cf(self.db.column(key.as_ref()), key, value)
+    }
+
+    pub fn delete<K: AsRef<[u8]>>(&self, key: K) -> Result<(), Error> {
+        self.txn.delete_cf(self.db.column(key.as_ref()), key)
+    }
+
+    /// Collects the keys starting with `prefix` in order.
+    pub fn scan_prefix(&self, prefix: &str) -> Result<Vec<(String, Vec<u8>)>, anyhow::Error> {
+        let mut items = vec![];
+        let cf = self.db.column(prefix.as_bytes());
+        for item in self.txn.prefix_iterator_cf(cf, prefix.as_bytes()) {
+            let (key, value) = item?;
+            if !key.starts_with(prefix.as_bytes()) {
+                break;
+            }
+            items.push((String::from_utf8(key.to_vec())?, value.to_vec()));
+        }
+        Ok(items)
+    }
+
+    pub fn commit(self) -> Result<(), Error> {
+        self.txn.commit()
+    }
+}
@@ -1,70 +1,119 @@
-use super::keys::Keys;
-use super::{DBInscription, IndexedRecord, Inscription};
+use super::codec::Codec;
+use super::columns::Txn;
+use super::journal::Journal;
+use super::keys::{keys_mint_index, Keys};
+use super::{
+    Balance, DBInscription, Ethscription, EthscriptionTransfer, IndexedRecord, Inscription,
+};
 use super::{Indexer, Tick};
 use crate::config::CHAINS_CONFIG;
-use crate::utils::remove_leadering_zeros;
+use crate::utils::{parse_amount, parse_decimals, remove_leadering_zeros};
 use anyhow::Ok;
-use async_trait::async_trait;
 use bigdecimal::{BigDecimal, Zero};
 use ethers::types::{Block, H256};
 use ethers::{abi::AbiEncode, types::Transaction};
 use log::warn;
-use rocksdb::TransactionDB;
 
-#[async_trait]
 pub trait Persistable {
-    async fn persist_deploy(
+    fn persist_deploy(
         &self,
+        txn: &Txn,
+        journal: &mut Journal,
         block: &Block<H256>,
         tx: &Transaction,
         inp: &Inscription,
     ) -> Result<(), anyhow::Error>;
-    async fn persist_mint(
+    fn persist_mint(
         &self,
+        txn: &Txn,
+        journal: &mut Journal,
         block: &Block<H256>,
         tx: &Transaction,
         inp: &Inscription,
     ) -> Result<(), anyhow::Error>;
+    fn persist_transfer(
+        &self,
+        txn: &Txn,
+        journal: &mut Journal,
+        block: &Block<H256>,
+        tx: &Transaction,
+        inp: &Inscription,
+    ) -> Result<(), anyhow::Error>;
+    fn persist_ethscription(
+        &self,
+        txn: &Txn,
+        journal: &mut Journal,
+        ethscription: &Ethscription,
+    ) -> Result<(), anyhow::Error>;
+    fn persist_ethscription_transfer(
+        &self,
+        txn: &Txn,
+        journal: &mut Journal,
+        tx: &Transaction,
+        transfers: &[EthscriptionTransfer],
+        previous_owner: Option<&str>,
+    ) -> Result<(), anyhow::Error>;
     fn persist_block(
         &self,
-        txn: &rocksdb::Transaction<TransactionDB>,
+        txn: &Txn,
         indexed_block: u64,
         indexed_txi: i64,
     ) -> Result<(), anyhow::Error>;
+    fn get_balance(
+        &self,
+        txn: &Txn,
+        p: &str,
+        tick: &str,
+        holder: &str,
+    ) -> Result<BigDecimal, anyhow::Error>;
+    fn persist_balance(
+        &self,
+        txn: &Txn,
+        journal: &mut Journal,
+        p: &str,
+        tick: &str,
+        holder: &str,
+        amount: BigDecimal,
+    ) -> Result<(), anyhow::Error>;
+    fn persist_journal(&self, txn: &Txn, journal: Journal) -> Result<(), anyhow::Error>;
 }
 
-#[async_trait]
 impl Persistable for Indexer {
-    async fn persist_deploy(
+    fn persist_deploy(
         &self,
+        txn: &Txn,
+        journal: &mut Journal,
         block: &Block<H256>,
         tx: &Transaction,
         inp: &Inscription,
     ) -> Result<(), anyhow::Error> {
-        let db = self.db.lock().await;
         let chain_id = tx.chain_id.unwrap().as_u64();
         let chain = CHAINS_CONFIG.get(&chain_id).unwrap().name.to_owned();
         let start_block = tx.block_number.unwrap().as_u64();
         let id: String = tx.hash.encode_hex();
         let tick_key = self.key_tick_deploy(&inp.p, &inp.tick);
-        let bs = db.get(tick_key.as_bytes())?;
+        let bs = txn.get(tick_key.as_bytes())?;
         if let Some(_) = bs {
             warn!("The tick has been deployed, just skip it!");
             return Ok(());
         }
-        let txn = db.transaction();
-        let max = inp
-            .max
-            .as_ref()
-            .unwrap()
-            .parse::<BigDecimal>()
-            .unwrap_or(BigDecimal::zero());
-        let lim = inp
-            .lim
-            .as_ref()
-            .unwrap()
-            .parse::<BigDecimal>()
-            .unwrap_or(BigDecimal::zero());
+        let rules = self.protocols.get(&inp.p).unwrap();
+        let dec = match inp.dec.as_ref() {
+            Some(dec) => parse_decimals(dec, rules.max_decimals()),
+            None => Some(rules.max_decimals()),
+        };
+        if dec.is_none() {
+            warn!("Invalid deploy cause of 'dec', just ignore it!");
+            return Ok(());
+        }
+        let dec = dec.unwrap();
+        let max = parse_amount(inp.max.as_ref().unwrap(), dec);
+        let lim = parse_amount(inp.lim.as_ref().unwrap(), dec);
+        if max.is_none() || lim.is_none() {
+            warn!("Invalid deploy cause of malformed 'max' or 'lim', just ignore it!");
+            return Ok(());
+        }
+        let (max, lim) = (max.unwrap(), lim.unwrap());
         if max.le(&BigDecimal::zero()) || lim.le(&BigDecimal::zero()) {
             warn!("Invalid deploy cause of 'max' or 'lim' lower than or equals to zero , just ignore it!");
             return Ok(());
@@ -82,53 +131,51 @@ impl Persistable for Indexer {
             tick: inp.tick.to_owned(),
             max: inp.max.to_owned(),
             lim: inp.lim.to_owned(),
+            dec,
             minted: "0".to_string(),
             holders: "0".to_string(),
             deployer: remove_leadering_zeros(tx.from.encode_hex()),
             timestamp: block.timestamp.as_u64(),
         };
-        let tick_value = serde_json::to_string(&tick).unwrap();
-        txn.put(tick_key.as_bytes(), tick_value.as_bytes())?;
-
-        // index block
-        self.persist_block(
-            &txn,
-            start_block,
-            tx.transaction_index.unwrap().as_u64() as i64,
-        )?;
-
-        txn.commit()?;
+        let tick_value = tick.encode();
+        journal.put(txn, &tick_key, &tick_value)?;
         Ok(())
     }
 
-    async fn persist_mint(
+    fn persist_mint(
         &self,
+        txn: &Txn,
+        journal: &mut Journal,
         block: &Block<H256>,
         tx: &Transaction,
         inp: &Inscription,
     ) -> Result<(), anyhow::Error> {
-        let db = self.db.lock().await;
         let chain_id = tx.chain_id.unwrap().as_u64();
         let chain = CHAINS_CONFIG.get(&chain_id).unwrap().name.to_owned();
         let blockno = tx.block_number.unwrap().as_u64();
         let id: String = tx.hash.encode_hex();
         let tick_key = self.key_tick_deploy(&inp.p, &inp.tick);
-        let bs = db.get(tick_key.as_bytes())?;
+        let bs = txn.get(tick_key.as_bytes())?;
         if let None = bs {
             warn!("Not found for deployed tick, just skip it!");
             return Ok(());
         }
-        let txn = db.transaction();
 
         // update tick
         let bs = txn.get(tick_key.as_bytes())?;
-        let mut tick: Tick = serde_json::from_slice(&bs.unwrap()).unwrap();
-        let amt = inp
-            .amt
-            .as_ref()
-            .unwrap()
-            .parse::<BigDecimal>()
-            .unwrap_or(BigDecimal::zero());
+        let mut tick = Tick::decode(&bs.unwrap())?;
+        let owner = remove_leadering_zeros(tx.from.encode_hex());
+        let rules = self.protocols.get(&inp.p).unwrap();
+        if rules.requires_deployer_mint() && owner.ne(&tick.deployer) {
+            warn!("Only the deployer is allowed to mint, just ignore it!");
+            return Ok(());
+        }
+        let amt = parse_amount(inp.amt.as_ref().unwrap(), tick.dec);
+        if amt.is_none() {
+            warn!("Invalid mint cause of malformed 'amt', just ignore it!");
+            return Ok(());
+        }
+        let amt = amt.unwrap();
         let lim = tick
             .lim
             .as_ref()
@@ -141,21 +188,20 @@ impl Persistable for Indexer {
         }
         let max = tick.max.as_ref().unwrap().parse::<BigDecimal>().unwrap();
         let minted = tick.minted.parse::<BigDecimal>().unwrap();
-        let updated_minted = minted + amt;
+        let updated_minted = minted + &amt;
         if updated_minted.gt(&max) {
             warn!("Max supply is reached, just ignore it!");
             return Ok(());
         }
-        tick.minted = updated_minted.to_string();
+        tick.minted = updated_minted.to_plain_string();
         if updated_minted.eq(&max) {
             tick.end_block = Some(tx.block_number.unwrap().as_u64());
             tick.mintable = false;
         }
-        let tick_value = serde_json::to_string(&tick).unwrap();
-        txn.put(tick_key.as_bytes(), tick_value.as_bytes())?;
+        let tick_value = tick.encode();
+        journal.put(txn, &tick_key, &tick_value)?;
 
         // insert mint
-        let owner = remove_leadering_zeros(tx.from.encode_hex());
         let insc = DBInscription {
             id,
             chain,
@@ -169,6 +215,7 @@ impl Persistable for Indexer {
             amt: inp.amt.to_owned(),
             owner: owner.to_owned(),
             timestamp: block.timestamp.as_u64(),
+            txi: tx.transaction_index.map(|x| x.as_u64()),
         };
         let insc_key = self.key_tick_mint(
             &inp.p,
@@ -177,18 +224,160 @@ impl Persistable for Indexer {
             &tx.hash.encode_hex(),
             block.timestamp.as_u64(),
         );
-        let insc_value = serde_json::to_string(&insc).unwrap();
-        txn.put(insc_key.as_bytes(), insc_value.as_bytes())?;
+        let insc_value = insc.encode();
+        journal.put(txn, &insc_key, &insc_value)?;
+        for index_key in keys_mint_index(self.chain_id, &insc).iter() {
+            journal.put(txn, index_key, insc_key.as_bytes())?;
+        }
+
+        // credit minter
+        let balance = self.get_balance(txn, &inp.p, &inp.tick, &owner)?;
+        self.persist_balance(txn, journal, &inp.p, &inp.tick, &owner, balance + amt)?;
+
+        Ok(())
+    }
+
+    fn persist_transfer(
+        &self,
+        txn: &Txn,
+        journal: &mut Journal,
+        _block: &Block<H256>,
+        tx: &Transaction,
+        inp: &Inscription,
+    ) -> Result<(), anyhow::Error> {
+        let tick_key = self.key_tick_deploy(&inp.p, &inp.tick);
+        let bs = txn.get(tick_key.as_bytes())?;
+        if let None = bs {
+            warn!("Not found for deployed tick, just skip it!");
+            return Ok(());
+        }
+        let tick = Tick::decode(&bs.unwrap())?;
+        let amt = parse_amount(inp.amt.as_ref().unwrap(), tick.dec);
+        if amt.is_none() {
+            warn!("Invalid transfer cause of malformed 'amt', just ignore it!");
+            return Ok(());
+        }
+        let amt = amt.unwrap();
+        if amt.le(&BigDecimal::zero()) {
+            warn!("Invalid transfer cause of 'amt' lower than or equals to zero, just ignore it!");
+            return Ok(());
+        }
+        let sender = remove_leadering_zeros(tx.from.encode_hex());
+        let recipient = remove_leadering_zeros(tx.to.unwrap().encode_hex());
+
+        // debit sender
+        let sender_balance = self.get_balance(txn, &inp.p, &inp.tick, &sender)?;
+        if sender_balance.lt(&amt) {
+            warn!("Insufficient balance for transfer, just ignore it!");
+            return Ok(());
+        }
+        self.persist_balance(
+            txn,
+            journal,
+            &inp.p,
+            &inp.tick,
+            &sender,
+            sender_balance - &amt,
+        )?;
+
+        // credit recipient
+        let recipient_balance = self.get_balance(txn, &inp.p, &inp.tick, &recipient)?;
+        self.persist_balance(
+            txn,
+            journal,
+            &inp.p,
+            &inp.tick,
+            &recipient,
+            recipient_balance + amt,
+        )?;
+
+        Ok(())
+    }
+
+    fn persist_ethscription(
+        &self,
+        txn: &Txn,
+        journal: &mut Journal,
+        ethscription: &Ethscription,
+    ) -> Result<(), anyhow::Error> {
+        let id = ethscription.id.as_str();
+        let content_key = self.key_ethscription_content(&ethscription.content_sha);
+        let bs = txn.get(content_key.as_bytes())?;
+        if let Some(_) = bs {
+            warn!("The content has been ethscribed, just skip it!");
+            return Ok(());
+        }
+        let ethscription_key = self.key_ethscription(id);
+        let bs = txn.get(ethscription_key.as_bytes())?;
+        if let Some(_) = bs {
+            warn!("The transaction has created an ethscription, just skip it!");
+            return Ok(());
+        }
+
+        // content-addressed store, the content sha points to its first ethscription
+        journal.put(txn, &content_key, id.as_bytes())?;
+
+        // insert ethscription
+        let ethscription_value = ethscription.encode();
+        journal.put(txn, &ethscription_key, &ethscription_value)?;
+        let owner_key = self.key_ethscription_owner(&ethscription.current_owner, id);
+        journal.put(txn, &owner_key, b"")?;
+
+        Ok(())
+    }
+
+    fn persist_ethscription_transfer(
+        &self,
+        txn: &Txn,
+        journal: &mut Journal,
+        tx: &Transaction,
+        transfers: &[EthscriptionTransfer],
+        previous_owner: Option<&str>,
+    ) -> Result<(), anyhow::Error> {
+        let blockno = tx.block_number.unwrap().as_u64();
+        let txi = tx.transaction_index.unwrap().as_u64();
+        for transfer in transfers.iter() {
+            let id = transfer.id.as_str();
+            let ethscription_key = self.key_ethscription(id);
+            let bs = txn.get(ethscription_key.as_bytes())?;
+            if let None = bs {
+                continue;
+            }
+            let mut ethscription = Ethscription::decode(&bs.unwrap())?;
+            if ethscription.current_owner.ne(&transfer.from) {
+                warn!("The sender doesn't own ethscription {}, just skip it!", id);
+                continue;
+            }
+            if previous_owner.is_some()
+                && ethscription.previous_owner.as_deref().ne(&previous_owner)
+            {
+                warn!(
+                    "Previous owner mismatched of ethscription {}, just skip it!",
+                    id
+                );
+                continue;
+            }
+            ethscription.previous_owner = Some(transfer.from.to_owned());
+            ethscription.current_owner = transfer.to.to_owned();
+            let ethscription_value = ethscription.encode();
+            journal.put(txn, &ethscription_key, &ethscription_value)?;
+
+            // move owner index
+            journal.delete(txn, &self.key_ethscription_owner(&transfer.from, id))?;
+            journal.put(txn, &self.key_ethscription_owner(&transfer.to, id), b"")?;
+
+            // append transfer history
+            let transfer_key = self.key_ethscription_transfer(id, blockno, txi, transfer.log_index);
+            let transfer_value = transfer.encode();
+            journal.put(txn, &transfer_key, &transfer_value)?;
+        }
 
-        // index block & txi
-        self.persist_block(&txn, blockno, tx.transaction_index.unwrap().as_u64() as i64)?;
-        txn.commit()?;
         Ok(())
     }
 
     fn persist_block(
         &self,
-        txn: &rocksdb::Transaction<TransactionDB>,
+        txn: &Txn,
         indexed_block: u64,
         indexed_txi: i64,
     ) -> Result<(), anyhow::Error> {
@@ -197,9 +386,221 @@ impl Persistable for Indexer {
             indexed_block,
             indexed_txi,
         };
-        let indexed_key = self.key_indexed_record();
-        let indexed_value = serde_json::to_string(&indexed_record).unwrap();
-        txn.put(indexed_key.as_bytes(), indexed_value.as_bytes())?;
+        let indexed_key = self.key_indexed_record(self.indexed_type);
+        let indexed_value = indexed_record.encode();
+        txn.put(indexed_key.as_bytes(), &indexed_value)?;
         Ok(())
     }
+
+    fn get_balance(
+        &self,
+        txn: &Txn,
+        p: &str,
+        tick: &str,
+        holder: &str,
+    ) -> Result<BigDecimal, anyhow::Error> {
+        let balance_key = self.key_tick_balance(p, tick, holder);
+        let bs = txn.get(balance_key.as_bytes())?;
+        if let None = bs {
+            return Ok(BigDecimal::zero());
+        }
+        let balance = Balance::decode(&bs.unwrap())?;
+        Ok(balance.amount.parse::<BigDecimal>().unwrap())
+    }
+
+    fn persist_balance(
+        &self,
+        txn: &Txn,
+        journal: &mut Journal,
+        p: &str,
+        tick: &str,
+        holder: &str,
+        amount: BigDecimal,
+    ) -> Result<(), anyhow::Error> {
+        let previous = self.get_balance(txn, p, tick, holder)?;
+        let balance_key = self.key_tick_balance(p, tick, holder);
+        if amount.is_zero() {
+            journal.delete(txn, &balance_key)?;
+        } else {
+            let balance = Balance {
+                chain_id: self.chain_id,
+                p: p.to_owned(),
+                tick: tick.to_owned(),
+                holder: holder.to_owned(),
+                amount: amount.to_plain_string(),
+            };
+            let balance_value = balance.encode();
+            journal.put(txn, &balance_key, &balance_value)?;
+        }
+
+        // update holders when a balance goes from zero to non-zero and back
+        let delta: i64 = match (previous.is_zero(), amount.is_zero()) {
+            (true, false) => 1,
+            (false, true) => -1,
+            _ => return Ok(()),
+        };
+        let tick_key = self.key_tick_deploy(p, tick);
+        let bs = txn.get(tick_key.as_bytes())?;
+        let mut tick = Tick::decode(&bs.unwrap())?;
+        let holders = tick.holders.parse::<i64>().unwrap_or(0) + delta;
+        tick.holders = holders.max(0).to_string();
+        let tick_value = tick.encode();
+        journal.put(txn, &tick_key, &tick_value)?;
+        Ok(())
+    }
+
+    fn persist_journal(&self, txn: &Txn, journal: Journal) -> Result<(), anyhow::Error> {
+        if journal.entries.is_empty() {
+            return Ok(());
+        }
+        let seq_key = self.key_undo_seq(journal.block);
+        let seq = txn
+            .get(seq_key.as_bytes())?
+            .map(|x| String::from_utf8(x).unwrap().parse::<u64>().unwrap())
+            .unwrap_or(0);
+        let undo_key = self.key_undo(journal.block, seq);
+        let undo_value = journal.entries.encode();
+        txn.put(undo_key.as_bytes(), &undo_value)?;
+        txn.put(seq_key.as_bytes(), (seq + 1).to_string().as_bytes())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{protocol::ProtocolRules, testing::*, IndexedType};
+    use super::*;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    /// Rules unlike those of any builtin protocol, so that every hook is taken.
+    struct Strict;
+
+    impl ProtocolRules for Strict {
+        fn name(&self) -> &'static str {
+            "strict-20"
+        }
+        fn max_decimals(&self) -> u32 {
+            2
+        }
+        fn requires_self_transaction(&self) -> bool {
+            false
+        }
+        fn requires_deployer_mint(&self) -> bool {
+            true
+        }
+    }
+
+    fn inscription(json: &str) -> String {
+        format!("data:,{}", json)
+    }
+
+    async fn balance(indexer: &Indexer, p: &str, tick: &str, holder: &str) -> String {
+        let db = indexer.db.lock().await;
+        let txn = Txn::new(&db);
+        let balance = indexer.get_balance(&txn, p, tick, holder).unwrap();
+        balance.to_plain_string()
+    }
+
+    #[tokio::test]
+    async fn applies_the_rules_of_a_registered_protocol() {
+        let dir = TempDir::new().unwrap();
+        let mut indexer = indexer(&open_db(dir.path()), IndexedType::TextPlain);
+        indexer.protocols.register(Arc::new(Strict));
+        let (deployer, other) = (address(1), address(2));
+        let deploy = |tick: &str, dec: &str| {
+            inscription(&format!(
+                r#"{{"p":"strict-20","op":"deploy","tick":"{}","max":"100","lim":"10"{}}}"#,
+                tick, dec
+            ))
+        };
+        let mint = |amt: &str| {
+            inscription(&format!(
+                r#"{{"p":"strict-20","op":"mint","tick":"abc","amt":"{}"}}"#,
+                amt
+            ))
+        };
+        let brc20 = r#"{"p":"brc-20","op":"deploy","tick":"ordi","max":"100","lim":"10"}"#;
+        let txs = vec![
+            // more decimals than the protocol allows
+            tx(1, 0, deployer, other, &deploy("xyz", r#","dec":"3""#)),
+            // not sent to the deployer itself
+            tx(1, 1, deployer, other, &deploy("abc", "")),
+            tx(1, 2, deployer, other, &inscription(brc20)),
+        ];
+        indexer.process_block(block(1, txs), -1).await.unwrap();
+        let key = indexer.key_tick_deploy("strict-20", "xyz");
+        assert!(get::<Tick>(&indexer, &key).await.is_none());
+        let key = indexer.key_tick_deploy("brc-20", "ordi");
+        assert!(get::<Tick>(&indexer, &key).await.is_none());
+        let key = indexer.key_tick_deploy("strict-20", "abc");
+        let tick: Tick = get(&indexer, &key).await.unwrap();
+        assert_eq!(tick.dec, 2);
+
+        let txs = vec![
+            tx(2, 0, other, other, &mint("5")),
+            tx(2, 1, deployer, other, &mint("1.234")),
+            tx(2, 2, deployer, other, &mint("1.23")),
+        ];
+        indexer.process_block(block(2, txs), -1).await.unwrap();
+        let tick: Tick = get(&indexer, &key).await.unwrap();
+        assert_eq!(tick.minted, "1.23");
+        let other = format!("{:?}", other);
+        let deployer = format!("{:?}", deployer);
+        assert_eq!(balance(&indexer, "strict-20", "abc", &other).await, "0");
+        assert_eq!(
+            balance(&indexer, "strict-20", "abc", &deployer).await,
+            "1.23"
+        );
+    }
+
+    #[tokio::test]
+    async fn transfers_within_the_balance_of_the_sender() {
+        let dir = TempDir::new().unwrap();
+        let indexer = indexer(&open_db(dir.path()), IndexedType::TextPlain);
+        let (a, b) = (address(1), address(2));
+        let transfer = |amt: &str| {
+            inscription(&format!(
+                r#"{{"p":"brc-20","op":"transfer","tick":"ordi","amt":"{}"}}"#,
+                amt
+            ))
+        };
+        let deploy = r#"{"p":"brc-20","op":"deploy","tick":"ordi","max":"100","lim":"10"}"#;
+        let mint = r#"{"p":"brc-20","op":"mint","tick":"ordi","amt":"10"}"#;
+        let txs = vec![
+            tx(1, 0, a, a, &inscription(deploy)),
+            tx(1, 1, a, a, &inscription(mint)),
+        ];
+        indexer.process_block(block(1, txs), -1).await.unwrap();
+        let tick_key = indexer.key_tick_deploy("brc-20", "ordi");
+        let holders = || async {
+            let tick: Tick = get(&indexer, &tick_key).await.unwrap();
+            tick.holders
+        };
+        let (ha, hb) = (format!("{:?}", a), format!("{:?}", b));
+
+        // more than the balance of the sender
+        let txs = vec![tx(2, 0, a, b, &transfer("10.5"))];
+        indexer.process_block(block(2, txs), -1).await.unwrap();
+        assert_eq!(balance(&indexer, "brc-20", "ordi", &ha).await, "10");
+        assert_eq!(balance(&indexer, "brc-20", "ordi", &hb).await, "0");
+
+        // to the sender itself
+        let txs = vec![tx(3, 0, a, a, &transfer("4"))];
+        indexer.process_block(block(3, txs), -1).await.unwrap();
+        assert_eq!(balance(&indexer, "brc-20", "ordi", &ha).await, "10");
+        assert_eq!(holders().await, "1");
+
+        // the whole balance moves away, then part of it comes back
+        let txs = vec![tx(4, 0, a, b, &transfer("10"))];
+        indexer.process_block(block(4, txs), -1).await.unwrap();
+        assert_eq!(balance(&indexer, "brc-20", "ordi", &ha).await, "0");
+        assert_eq!(balance(&indexer, "brc-20", "ordi", &hb).await, "10");
+        assert_eq!(holders().await, "1");
+        let txs = vec![tx(5, 0, b, a, &transfer("2.5"))];
+        indexer.process_block(block(5, txs), -1).await.unwrap();
+        assert_eq!(balance(&indexer, "brc-20", "ordi", &ha).await, "2.5");
+        assert_eq!(balance(&indexer, "brc-20", "ordi", &hb).await, "7.5");
+        assert_eq!(holders().await, "2");
+    }
 }
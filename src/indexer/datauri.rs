@@ -0,0 +1,224 @@
+use anyhow::anyhow;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::str::FromStr;
+
+pub const SCHEME_DATA: &'static str = "data:";
+pub const DEFAULT_MEDIATYPE: &'static str = "text/plain";
+pub const DEFAULT_CHARSET: &'static str = "US-ASCII";
+
+/// A parsed RFC 2397 `data:` URI.
+///
+/// `dataurl := "data:" [ mediatype ] [ ";base64" ] "," data`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataUri {
+    /// Lowercased `type/subtype`, `text/plain` when omitted.
+    pub mediatype: String,
+    /// Parameters in the order they appear, attribute names lowercased.
+    pub parameters: Vec<(String, String)>,
+    pub base64: bool,
+    /// Decoded payload bytes.
+    pub data: Vec<u8>,
+}
+
+impl DataUri {
+    pub fn parse(input: &[u8]) -> Result<Self, anyhow::Error> {
+        if input.len() < SCHEME_DATA.len()
+            || !input[..SCHEME_DATA.len()].eq_ignore_ascii_case(SCHEME_DATA.as_bytes())
+        {
+            return Err(anyhow!("Missing 'data:' scheme"));
+        }
+        let input = &input[SCHEME_DATA.len()..];
+        let comma = input
+            .iter()
+            .position(|x| *x == b',')
+            .ok_or(anyhow!("Missing ',' separator"))?;
+        let header = std::str::from_utf8(&input[..comma])?;
+        let payload = &input[comma + 1..];
+
+        let mut segments = header.split(';');
+        let mediatype = segments.next().unwrap_or("").trim().to_ascii_lowercase();
+        let mediatype = if mediatype.is_empty() {
+            DEFAULT_MEDIATYPE.to_string()
+        } else if is_valid_mediatype(&mediatype) {
+            mediatype
+        } else {
+            return Err(anyhow!("Invalid mediatype {}", mediatype));
+        };
+        let mut parameters = vec![];
+        let mut base64 = false;
+        let mut segments = segments.peekable();
+        while let Some(segment) = segments.next() {
+            // ';base64' is only an extension when it is the last segment
+            if segments.peek().is_none() && segment.eq_ignore_ascii_case("base64") {
+                base64 = true;
+                break;
+            }
+            let (attribute, value) = segment
+                .split_once('=')
+                .ok_or(anyhow!("Invalid parameter {}", segment))?;
+            if attribute.is_empty() {
+                return Err(anyhow!("Invalid parameter {}", segment));
+            }
+            parameters.push((attribute.to_ascii_lowercase(), value.to_string()));
+        }
+
+        let decoded = percent_decode(payload);
+        let data = if base64 {
+            let stripped: Vec<u8> = decoded
+                .into_iter()
+                .filter(|x| !x.is_ascii_whitespace())
+                .collect();
+            STANDARD.decode(stripped)?
+        } else {
+            decoded
+        };
+        Ok(DataUri {
+            mediatype,
+            parameters,
+            base64,
+            data,
+        })
+    }
+
+    pub fn parameter(&self, attribute: &str) -> Option<&str> {
+        self.parameters
+            .iter()
+            .find(|(x, _)| x.eq_ignore_ascii_case(attribute))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn charset(&self) -> &str {
+        match self.parameter("charset") {
+            Some(charset) => charset,
+            None if self.mediatype.eq(DEFAULT_MEDIATYPE) => DEFAULT_CHARSET,
+            None => "utf-8",
+        }
+    }
+
+    /// Decodes the payload as text, only UTF-8 and its ASCII subset are supported.
+    pub fn text(&self) -> Result<String, anyhow::Error> {
+        let charset = self.charset().to_ascii_lowercase();
+        match charset.as_str() {
+            "utf-8" | "utf8" | "us-ascii" | "ascii" => Ok(String::from_utf8(self.data.to_vec())?),
+            _ => Err(anyhow!("Unsupported charset {}", charset)),
+        }
+    }
+}
+
+impl FromStr for DataUri {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DataUri::parse(s.as_bytes())
+    }
+}
+
+fn is_valid_mediatype(mediatype: &str) -> bool {
+    let is_token = |x: &str| {
+        !x.is_empty()
+            && x.chars()
+                .all(|c| c.is_ascii_alphanumeric() || "!#$&-^_.+".contains(c))
+    };
+    match mediatype.split_once('/') {
+        Some((ty, subtype)) => is_token(ty) && is_token(subtype),
+        None => false,
+    }
+}
+
+/// Decodes `%XX` escapes, malformed escapes are kept verbatim.
+fn percent_decode(input: &[u8]) -> Vec<u8> {
+    let hex = |x: u8| (x as char).to_digit(16).map(|x| x as u8);
+    let mut decoded = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        if input[i] == b'%' && i + 2 < input.len() {
+            if let (Some(hi), Some(lo)) = (hex(input[i + 1]), hex(input[i + 2])) {
+                decoded.push(hi << 4 | lo);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(input[i]);
+        i += 1;
+    }
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_to_us_ascii_text() {
+        let uri = DataUri::parse(b"data:,hello").unwrap();
+        assert_eq!(uri.mediatype, DEFAULT_MEDIATYPE);
+        assert_eq!(uri.charset(), DEFAULT_CHARSET);
+        assert!(!uri.base64);
+        assert_eq!(uri.text().unwrap(), "hello");
+    }
+
+    #[test]
+    fn defaults_to_utf8_for_other_mediatypes() {
+        let uri = DataUri::parse(b"DATA:Application/JSON,{}").unwrap();
+        assert_eq!(uri.mediatype, "application/json");
+        assert_eq!(uri.charset(), "utf-8");
+    }
+
+    #[test]
+    fn parses_parameters() {
+        let uri = DataUri::parse(b"data:text/plain;Charset=utf-8;foo=bar,x").unwrap();
+        assert_eq!(uri.charset(), "utf-8");
+        assert_eq!(uri.parameter("foo"), Some("bar"));
+        let uri = DataUri::parse(b"data:;charset=utf-8,x").unwrap();
+        assert_eq!(uri.mediatype, DEFAULT_MEDIATYPE);
+        assert!(DataUri::parse(b"data:text/plain;charset,x").is_err());
+        assert!(DataUri::parse(b"data:text,x").is_err());
+        assert!(DataUri::parse(b"data:text/plain").is_err());
+    }
+
+    #[test]
+    fn base64_only_as_last_segment() {
+        let uri = DataUri::parse(b"data:text/plain;charset=utf-8;base64,aGk=").unwrap();
+        assert!(uri.base64);
+        assert_eq!(uri.data, b"hi");
+        let uri = DataUri::parse(b"data:;BASE64,aGk=").unwrap();
+        assert!(uri.base64);
+        assert_eq!(uri.data, b"hi");
+        assert!(DataUri::parse(b"data:text/plain;base64;charset=utf-8,aGk=").is_err());
+        assert!(DataUri::parse(b"data:;base64,not base64!").is_err());
+    }
+
+    #[test]
+    fn decodes_base64_after_percent_decoding() {
+        let uri = DataUri::parse(b"data:;base64,aG%6B=%0A").unwrap();
+        assert_eq!(uri.data, b"hi");
+    }
+
+    #[test]
+    fn percent_decodes_payload() {
+        let uri = DataUri::parse(b"data:,a%20b%2C%e2%82%AC").unwrap();
+        assert_eq!(uri.text().unwrap(), "a b,\u{20ac}");
+        let uri = DataUri::parse(b"data:,%41").unwrap();
+        assert_eq!(uri.data, b"A");
+    }
+
+    #[test]
+    fn keeps_malformed_escapes() {
+        let uri = DataUri::parse(b"data:,100%").unwrap();
+        assert_eq!(uri.data, b"100%");
+        let uri = DataUri::parse(b"data:,%4").unwrap();
+        assert_eq!(uri.data, b"%4");
+        let uri = DataUri::parse(b"data:,%zz%%41").unwrap();
+        assert_eq!(uri.data, b"%zz%A");
+    }
+
+    #[test]
+    fn percent_decodes_inscriptions() {
+        // the JSON of a `data:,` inscription is percent-decoded before it is parsed
+        let uri = DataUri::parse(br#"data:,{"p":"brc-20","op":"mint","tick":"a%62cd","amt":"1"}"#)
+            .unwrap();
+        assert_eq!(
+            uri.text().unwrap(),
+            r#"{"p":"brc-20","op":"mint","tick":"abcd","amt":"1"}"#
+        );
+    }
+}
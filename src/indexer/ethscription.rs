@@ -0,0 +1,414 @@
+use super::{
+    columns::Txn, database::Persistable, datauri::DataUri, journal::Journal, Ethscription,
+    EthscriptionTransfer, Indexer,
+};
+use crate::config::{ChainId, CHAINS_CONFIG};
+use crate::utils::{h256_to_h160, remove_leadering_zeros};
+use anyhow::Ok;
+use ethers::{
+    abi::{self, AbiEncode, ParamType},
+    providers::Middleware,
+    types::{Block, Filter as LogFilter, Log, Transaction, H160, H256},
+    utils::{hex, keccak256},
+};
+use log::warn;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+lazy_static! {
+    // ESIP-3
+    pub static ref TOPIC_CREATE_ETHSCRIPTION: H256 = H256::from(keccak256(
+        "ethscriptions_protocol_CreateEthscription(address,string)"
+    ));
+    // ESIP-1
+    pub static ref TOPIC_TRANSFER_ETHSCRIPTION: H256 = H256::from(keccak256(
+        "ethscriptions_protocol_TransferEthscription(address,bytes32)"
+    ));
+    // ESIP-2
+    pub static ref TOPIC_TRANSFER_ETHSCRIPTION_FOR_PREVIOUS_OWNER: H256 = H256::from(keccak256(
+        "ethscriptions_protocol_TransferEthscriptionForPreviousOwner(address,address,bytes32)"
+    ));
+}
+
+impl Indexer {
+    /// Indexes any valid data URI sent to an address as an ethscription,
+    /// the content of an ethscription must be unique.
+    pub fn process_ethscription(
+        &self,
+        txn: &Txn,
+        journal: &mut Journal,
+        block: &Block<H256>,
+        tx: &Transaction,
+    ) -> Result<(), anyhow::Error> {
+        if tx.to.is_none() {
+            return Ok(());
+        }
+        if self.filter.recipient.is_some() && tx.to.unwrap().ne(&self.filter.recipient.unwrap()) {
+            return Ok(());
+        }
+        // the content uri must be a valid utf-8 string
+        let content_uri = String::from_utf8(tx.input.to_vec()).unwrap_or_default();
+        let data_uri = DataUri::parse(content_uri.as_bytes());
+        if data_uri.is_err() {
+            return self.process_ethscription_transfer(txn, journal, block, tx);
+        }
+        let ethscription = ethscription(
+            self.chain_id,
+            block,
+            tx,
+            tx.from,
+            tx.to.unwrap(),
+            &data_uri.unwrap(),
+            &content_uri,
+        );
+        self.persist_ethscription(txn, journal, &ethscription)?;
+        Ok(())
+    }
+
+    /// Calldata of exactly one or several concatenated 32-byte ethscription ids
+    /// transfers those ethscriptions from the sender to the recipient.
+    fn process_ethscription_transfer(
+        &self,
+        txn: &Txn,
+        journal: &mut Journal,
+        block: &Block<H256>,
+        tx: &Transaction,
+    ) -> Result<(), anyhow::Error> {
+        if tx.input.is_empty() || tx.input.len() % 32 != 0 {
+            return Ok(());
+        }
+        let transfers = tx
+            .input
+            .chunks(32)
+            .map(|x| {
+                let id = H256::from_slice(x).encode_hex();
+                ethscription_transfer(block, tx, id, tx.from, tx.to.unwrap(), None)
+            })
+            .collect::<Vec<EthscriptionTransfer>>();
+        self.persist_ethscription_transfer(txn, journal, tx, &transfers, None)?;
+        Ok(())
+    }
+
+    /// Fetches the ethscriptions protocol events emitted in a range of blocks,
+    /// grouped by block number and transaction index.
+    pub async fn get_ethscription_logs(
+        &self,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<HashMap<u64, HashMap<u64, Vec<Log>>>, anyhow::Error> {
+        let filter = LogFilter::new()
+            .from_block(from_block)
+            .to_block(to_block)
+            .topic0(ethscription_topics().to_vec());
+        let filter = &filter;
+        let logs = self
+            .https
+            .call(
+                to_block,
+                |x| async move { x.provider.get_logs(filter).await },
+            )
+            .await?;
+        Ok(group_ethscription_logs(logs))
+    }
+
+    /// Fetches the ethscriptions protocol events emitted in the block of `block_hash`,
+    /// grouped by transaction index.
+    pub async fn get_ethscription_logs_at(
+        &self,
+        block: u64,
+        block_hash: H256,
+    ) -> Result<HashMap<u64, Vec<Log>>, anyhow::Error> {
+        let filter = LogFilter::new()
+            .at_block_hash(block_hash)
+            .topic0(ethscription_topics().to_vec());
+        let filter = &filter;
+        let logs = self
+            .https
+            .call(block, |x| async move { x.provider.get_logs(filter).await })
+            .await?;
+        Ok(group_ethscription_logs(logs)
+            .remove(&block)
+            .unwrap_or_default())
+    }
+
+    /// Applies the ethscriptions emitted or moved by contracts, e.g. escrows and marketplaces,
+    /// the emitting contract acts as the creator or the sender. Events only move ethscriptions,
+    /// the balances of the ledger modes only move by transfer inscriptions.
+    pub fn process_ethscription_events(
+        &self,
+        txn: &Txn,
+        journal: &mut Journal,
+        block: &Block<H256>,
+        tx: &Transaction,
+        logs: &[Log],
+    ) -> Result<(), anyhow::Error> {
+        for log in logs.iter() {
+            let log_index = log.log_index.map(|x| x.as_u64());
+            match decode_ethscription_event(log) {
+                Some(EthscriptionEvent::Create {
+                    initial_owner,
+                    content_uri,
+                }) => {
+                    let data_uri = DataUri::parse(content_uri.as_bytes());
+                    if data_uri.is_err() {
+                        continue;
+                    }
+                    let ethscription = ethscription(
+                        self.chain_id,
+                        block,
+                        tx,
+                        log.address,
+                        initial_owner,
+                        &data_uri.unwrap(),
+                        &content_uri,
+                    );
+                    self.persist_ethscription(txn, journal, &ethscription)?;
+                }
+                Some(EthscriptionEvent::Transfer { to, id }) => {
+                    let transfer = ethscription_transfer(block, tx, id, log.address, to, log_index);
+                    self.persist_ethscription_transfer(txn, journal, tx, &[transfer], None)?;
+                }
+                Some(EthscriptionEvent::TransferForPreviousOwner {
+                    previous_owner,
+                    to,
+                    id,
+                }) => {
+                    let previous_owner = remove_leadering_zeros(previous_owner.encode_hex());
+                    let transfer = ethscription_transfer(block, tx, id, log.address, to, log_index);
+                    self.persist_ethscription_transfer(
+                        txn,
+                        journal,
+                        tx,
+                        &[transfer],
+                        Some(&previous_owner),
+                    )?;
+                }
+                None => continue,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum EthscriptionEvent {
+    /// ESIP-3
+    Create {
+        initial_owner: H160,
+        content_uri: String,
+    },
+    /// ESIP-1
+    Transfer { to: H160, id: String },
+    /// ESIP-2
+    TransferForPreviousOwner {
+        previous_owner: H160,
+        to: H160,
+        id: String,
+    },
+}
+
+/// Decodes an ethscriptions protocol event, `None` for other or malformed logs.
+pub fn decode_ethscription_event(log: &Log) -> Option<EthscriptionEvent> {
+    let topic0 = log.topics.first().copied().unwrap_or_default();
+    if topic0.eq(&TOPIC_CREATE_ETHSCRIPTION) && log.topics.len() == 2 {
+        let decoded = abi::decode(&[ParamType::String], &log.data);
+        if decoded.is_err() {
+            warn!("Invalid CreateEthscription event, just skip it!");
+            return None;
+        }
+        Some(EthscriptionEvent::Create {
+            initial_owner: h256_to_h160(log.topics[1]),
+            content_uri: decoded.unwrap().remove(0).into_string().unwrap(),
+        })
+    } else if topic0.eq(&TOPIC_TRANSFER_ETHSCRIPTION) && log.topics.len() == 3 {
+        Some(EthscriptionEvent::Transfer {
+            to: h256_to_h160(log.topics[1]),
+            id: log.topics[2].encode_hex(),
+        })
+    } else if topic0.eq(&TOPIC_TRANSFER_ETHSCRIPTION_FOR_PREVIOUS_OWNER) && log.topics.len() == 4 {
+        Some(EthscriptionEvent::TransferForPreviousOwner {
+            previous_owner: h256_to_h160(log.topics[1]),
+            to: h256_to_h160(log.topics[2]),
+            id: log.topics[3].encode_hex(),
+        })
+    } else {
+        None
+    }
+}
+
+/// Groups the ethscriptions protocol events by block number and transaction index,
+/// other logs are dropped.
+pub fn group_ethscription_logs(logs: Vec<Log>) -> HashMap<u64, HashMap<u64, Vec<Log>>> {
+    let topics = ethscription_topics();
+    let mut grouped: HashMap<u64, HashMap<u64, Vec<Log>>> = HashMap::new();
+    for log in logs.into_iter() {
+        if log.removed.unwrap_or(false)
+            || log.block_number.is_none()
+            || log.transaction_index.is_none()
+            || !log.topics.first().is_some_and(|x| topics.contains(x))
+        {
+            continue;
+        }
+        let block = log.block_number.unwrap().as_u64();
+        let txi = log.transaction_index.unwrap().as_u64();
+        grouped
+            .entry(block)
+            .or_default()
+            .entry(txi)
+            .or_default()
+            .push(log);
+    }
+    for logs in grouped.values_mut().flat_map(|x| x.values_mut()) {
+        logs.sort_by(|x, y| x.log_index.cmp(&y.log_index));
+    }
+    grouped
+}
+
+fn ethscription_topics() -> [H256; 3] {
+    [
+        *TOPIC_CREATE_ETHSCRIPTION,
+        *TOPIC_TRANSFER_ETHSCRIPTION,
+        *TOPIC_TRANSFER_ETHSCRIPTION_FOR_PREVIOUS_OWNER,
+    ]
+}
+
+fn content_sha(content_uri: &str) -> String {
+    format!("0x{}", hex::encode(Sha256::digest(content_uri.as_bytes())))
+}
+
+fn ethscription(
+    chain_id: ChainId,
+    block: &Block<H256>,
+    tx: &Transaction,
+    creator: H160,
+    initial_owner: H160,
+    data_uri: &DataUri,
+    content_uri: &str,
+) -> Ethscription {
+    let owner = remove_leadering_zeros(initial_owner.encode_hex());
+    Ethscription {
+        id: tx.hash.encode_hex(),
+        chain_id,
+        chain: CHAINS_CONFIG.get(&chain_id).unwrap().name.to_owned(),
+        content_sha: content_sha(content_uri),
+        mimetype: data_uri.mediatype.to_owned(),
+        creator: remove_leadering_zeros(creator.encode_hex()),
+        initial_owner: owner.to_owned(),
+        current_owner: owner,
+        previous_owner: None,
+        block: tx.block_number.unwrap().as_u64(),
+        txi: tx.transaction_index.unwrap().as_u64(),
+        timestamp: block.timestamp.as_u64(),
+    }
+}
+
+fn ethscription_transfer(
+    block: &Block<H256>,
+    tx: &Transaction,
+    id: String,
+    from: H160,
+    to: H160,
+    log_index: Option<u64>,
+) -> EthscriptionTransfer {
+    EthscriptionTransfer {
+        id,
+        from: remove_leadering_zeros(from.encode_hex()),
+        to: remove_leadering_zeros(to.encode_hex()),
+        hash: tx.hash.encode_hex(),
+        block: tx.block_number.unwrap().as_u64(),
+        txi: tx.transaction_index.unwrap().as_u64(),
+        log_index,
+        timestamp: block.timestamp.as_u64(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::abi::Token;
+
+    fn topic(address: H160) -> H256 {
+        H256::from(address)
+    }
+
+    fn log(topics: Vec<H256>, data: Vec<u8>) -> Log {
+        Log {
+            topics,
+            data: data.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn decodes_create_ethscription() {
+        let owner = H160::repeat_byte(0x11);
+        let data = abi::encode(&[Token::String("data:,hello".to_string())]);
+        let event =
+            decode_ethscription_event(&log(vec![*TOPIC_CREATE_ETHSCRIPTION, topic(owner)], data));
+        assert_eq!(
+            event,
+            Some(EthscriptionEvent::Create {
+                initial_owner: owner,
+                content_uri: "data:,hello".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn decodes_transfer_ethscription() {
+        let to = H160::repeat_byte(0x22);
+        let id = H256::repeat_byte(0x33);
+        let event = decode_ethscription_event(&log(
+            vec![*TOPIC_TRANSFER_ETHSCRIPTION, topic(to), id],
+            vec![],
+        ));
+        assert_eq!(
+            event,
+            Some(EthscriptionEvent::Transfer {
+                to,
+                id: id.encode_hex(),
+            })
+        );
+    }
+
+    #[test]
+    fn decodes_transfer_ethscription_for_previous_owner() {
+        let previous_owner = H160::repeat_byte(0x44);
+        let to = H160::repeat_byte(0x55);
+        let id = H256::repeat_byte(0x66);
+        let event = decode_ethscription_event(&log(
+            vec![
+                *TOPIC_TRANSFER_ETHSCRIPTION_FOR_PREVIOUS_OWNER,
+                topic(previous_owner),
+                topic(to),
+                id,
+            ],
+            vec![],
+        ));
+        assert_eq!(
+            event,
+            Some(EthscriptionEvent::TransferForPreviousOwner {
+                previous_owner,
+                to,
+                id: id.encode_hex(),
+            })
+        );
+    }
+
+    #[test]
+    fn skips_malformed_events() {
+        let to = H160::repeat_byte(0x22);
+        assert_eq!(
+            decode_ethscription_event(&log(vec![*TOPIC_TRANSFER_ETHSCRIPTION, topic(to)], vec![])),
+            None
+        );
+        assert_eq!(
+            decode_ethscription_event(&log(
+                vec![*TOPIC_CREATE_ETHSCRIPTION, topic(to)],
+                vec![0x01]
+            )),
+            None
+        );
+        assert_eq!(decode_ethscription_event(&log(vec![], vec![])), None);
+    }
+}
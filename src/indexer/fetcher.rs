@@ -0,0 +1,91 @@
+use super::{IndexedType, Indexer};
+use crate::pool::Endpoint;
+use ethers::{
+    providers::Middleware,
+    types::{Block, Log, Transaction, H256, U64},
+};
+use futures::future::try_join_all;
+use log::warn;
+use serde_json::json;
+use std::collections::HashMap;
+
+/// A block fetched ahead of being applied, with everything needed to apply it.
+pub struct FetchedBlock {
+    pub block: Block<Transaction>,
+    pub logs: HashMap<u64, Vec<Log>>,
+    /// Statuses of the transactions when the receipts came with the block.
+    pub statuses: Option<HashMap<H256, bool>>,
+}
+
+impl Indexer {
+    /// Fetches the blocks from `from` to `to` with their transactions and events,
+    /// `None` for the blocks which aren't available yet.
+    /// Ranges are spread over the healthy providers which have reached `to`.
+    pub async fn fetch_blocks(
+        &self,
+        from: u64,
+        to: u64,
+    ) -> Result<Vec<Option<FetchedBlock>>, anyhow::Error> {
+        if !self.https.has_block(to) {
+            return Ok(vec![None]);
+        }
+        let nth = (from / self.batch_size as u64) as usize;
+        let blocks = self
+            .https
+            .call_nth(nth, to, |x| async move { get_blocks(&x, from, to).await })
+            .await?;
+        let mut logs = if self.indexed_type == IndexedType::Ethscription {
+            self.get_ethscription_logs(from, to).await?
+        } else {
+            HashMap::new()
+        };
+        let mut fetched = vec![];
+        for (block, number) in blocks.into_iter().zip(from..=to) {
+            if block.is_none() {
+                fetched.push(None);
+                continue;
+            }
+            let block = block.unwrap();
+            let mut block_logs = logs.remove(&number).unwrap_or_default();
+            // logs may come from a provider on another fork, then they are fetched by block hash
+            if block_logs
+                .values()
+                .flatten()
+                .any(|x| x.block_hash.ne(&block.hash))
+            {
+                warn!("Logs of block {} are of another fork, refetch them", number);
+                block_logs = self
+                    .get_ethscription_logs_at(number, block.hash.unwrap())
+                    .await?;
+            }
+            fetched.push(Some(FetchedBlock {
+                block,
+                logs: block_logs,
+                statuses: None,
+            }));
+        }
+        Ok(fetched)
+    }
+}
+
+/// Fetches a range of blocks in a single JSON-RPC batch,
+/// falling back to one request per block if the provider rejects batches.
+async fn get_blocks(
+    endpoint: &Endpoint,
+    from: u64,
+    to: u64,
+) -> Result<Vec<Option<Block<Transaction>>>, anyhow::Error> {
+    if from < to && !endpoint.batch.is_unsupported() {
+        let params = (from..=to).map(|x| json!([U64::from(x), true])).collect();
+        let blocks = endpoint
+            .batch
+            .batch::<Option<Block<Transaction>>>("eth_getBlockByNumber", params)
+            .await;
+        if blocks.is_ok() || !endpoint.batch.is_unsupported() {
+            return blocks;
+        }
+        warn!("{}, fallback to single requests", blocks.err().unwrap());
+    }
+    let blocks = try_join_all((from..=to).map(|x| endpoint.provider.get_block_with_txs(x))).await?;
+    Ok(blocks)
+}
@@ -0,0 +1,81 @@
+use super::Indexer;
+use crate::pool::ProviderPool;
+use ethers::providers::{Middleware, Provider, StreamExt, Ws};
+use log::{info, warn};
+use std::{sync::Arc, time::Duration};
+use tokio::{
+    sync::watch,
+    time::{timeout, timeout_at, Instant},
+};
+
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+// a socket without any head for this long is considered dropped
+const HEAD_TIMEOUT: Duration = Duration::from_secs(120);
+
+impl Indexer {
+    /// Notifies new heads from the `newHeads` subscription of the chain's wss endpoint,
+    /// the value is the head number, or `None` when it was polled over HTTP while the socket is down.
+    /// Heads arriving while the indexer is busy are coalesced into the latest one.
+    pub fn subscribe_heads(&self) -> watch::Receiver<Option<u64>> {
+        let (sender, receiver) = watch::channel(None);
+        let wss = self.wss.to_owned();
+        let https = self.https.to_owned();
+        tokio::spawn(async move {
+            let mut delay = MIN_RECONNECT_DELAY;
+            while !sender.is_closed() {
+                if !wss.is_empty() {
+                    match subscribe_new_heads(&wss, &sender).await {
+                        Ok(()) => {
+                            warn!("Subscription of new heads on {} dropped", wss);
+                            delay = MIN_RECONNECT_DELAY;
+                        }
+                        Err(e) => warn!("Failed to subscribe new heads on {}: {}", wss, e),
+                    }
+                    if sender.is_closed() {
+                        break;
+                    }
+                    warn!("Poll new heads over http, reconnect in {:?}", delay);
+                }
+                if let Err(e) = poll_new_heads(&https, &sender, delay).await {
+                    warn!("Failed to poll new heads: {}", e);
+                    tokio::time::sleep(MIN_RECONNECT_DELAY).await;
+                }
+                delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+            }
+        });
+        receiver
+    }
+}
+
+async fn subscribe_new_heads(
+    wss: &str,
+    sender: &watch::Sender<Option<u64>>,
+) -> Result<(), anyhow::Error> {
+    let provider = Provider::<Ws>::connect(wss).await?;
+    let mut stream = provider.subscribe_blocks().await?;
+    info!("Subscribed new heads on {}", wss);
+    while let Ok(Some(head)) = timeout(HEAD_TIMEOUT, stream.next()).await {
+        if sender.send(head.number.map(|x| x.as_u64())).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Polls new heads over http for `duration`, the fallback of the subscription.
+async fn poll_new_heads(
+    https: &Arc<ProviderPool>,
+    sender: &watch::Sender<Option<u64>>,
+    duration: Duration,
+) -> Result<(), anyhow::Error> {
+    let endpoint = https.best()?;
+    let mut stream = endpoint.provider.watch_blocks().await?;
+    let deadline = Instant::now() + duration;
+    while let Ok(Some(_)) = timeout_at(deadline, stream.next()).await {
+        if sender.send(None).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
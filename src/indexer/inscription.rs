@@ -1,37 +1,57 @@
+use super::columns::Txn;
+use super::keys::Keys;
 use super::{
-    database::Persistable, Indexer, Inscription, InscriptionFieldValidate, OP_DEPLOY, OP_MINT,
-    PREFIX_INSCRIPTION,
+    database::Persistable,
+    datauri::{DataUri, SCHEME_DATA},
+    fetcher::FetchedBlock,
+    journal::Journal,
+    receipts::BlockReceipts,
+    IndexedType, Indexer, Inscription, InscriptionFieldValidate, OP_DEPLOY, OP_MINT, OP_TRANSFER,
 };
-use crate::config::Random;
+use crate::config::HeadTag;
 use anyhow::{anyhow, Ok};
 use ethers::{
+    abi::AbiEncode,
     providers::{Middleware, StreamExt},
     types::{Block, BlockNumber, Transaction, H256},
 };
+use futures::stream;
 use log::{info, warn};
 
 impl Indexer {
+    /// Follows the chain from the cursor until a shutdown is requested.
+    /// `START_BLOCK` only applies when nothing was indexed yet, so a restart resumes from the cursor.
     pub async fn index_inscriptions(&self) -> Result<(), anyhow::Error> {
-        let (indexed_block, mut block_txi): (u64, i64) =
-            self.get_indexed_block(self.indexed_type.to_owned()).await;
-        let mut block_to_process = indexed_block;
-        if self.filter.start_block.is_some() && self.filter.start_block.unwrap() != block_to_process
-        {
-            block_to_process = self.filter.start_block.unwrap();
-        }
-        let provider = self.https.random().unwrap();
-        let mut block_stream = provider.watch_blocks().await?;
-        let next_block = |block, _| (block + 1, -1);
-        'stop_indexing: while block_stream.next().await.is_some() {
-            let block = self
-                .https
-                .random()
-                .unwrap()
-                .get_block(BlockNumber::Latest)
-                .await?
-                .unwrap();
-            let block_number = block.number.unwrap();
-            while block_to_process <= block_number.as_u64() {
+        let (mut block_to_process, mut block_txi): (u64, i64) =
+            self.get_indexed_block(self.indexed_type.to_owned()).await?;
+        let mut heads = self.subscribe_heads();
+        let mut shutdown = self.shutdown.to_owned();
+        'stop_indexing: loop {
+            tokio::select! {
+                changed = heads.changed() => changed?,
+                _ = shutdown.wait_for(|x| *x) => break 'stop_indexing,
+            }
+            let subscribed_head = *heads.borrow_and_update();
+            self.https.refresh_heads().await;
+            // never go beyond the head of the providers blocks are fetched from
+            let head = match subscribed_head {
+                Some(head) if self.head == HeadTag::Latest => head.min(self.https.head()),
+                _ if self.head == HeadTag::Latest => self.https.head(),
+                _ => {
+                    let tag = BlockNumber::from(self.head);
+                    let head = self
+                        .https
+                        .call(0, |x| async move { x.provider.get_block(tag).await })
+                        .await?;
+                    if head.is_none() {
+                        warn!("Block {:?} not found, wait for it", self.head);
+                        continue;
+                    }
+                    head.unwrap().number.unwrap().as_u64()
+                }
+            };
+            let block_number = head.saturating_sub(self.confirmations);
+            'fetching: while block_to_process <= block_number {
                 if self.filter.end_block.is_some()
                     && block_to_process > self.filter.end_block.unwrap()
                 {
@@ -41,110 +61,266 @@ impl Indexer {
                     );
                     break 'stop_indexing;
                 }
-                info!("Process block {}", block_to_process);
-                let txs = self
-                    .https
-                    .random()
-                    .unwrap()
-                    .get_block_with_txs(block_to_process)
-                    .await?;
-                if let None = txs {
-                    self.mark_block_and_txi(block_to_process, block_txi).await?;
-                    (block_to_process, block_txi) = next_block(block_to_process, block_txi);
-                    continue;
-                }
-                let mut txs = txs.unwrap().transactions;
-                txs = txs
-                    .into_iter()
-                    .filter(|tx| tx.transaction_index.unwrap().as_u64() as i64 > block_txi)
-                    .collect::<Vec<Transaction>>();
-                txs.sort_by(|x, y| x.transaction_index.cmp(&y.transaction_index));
-                for tx in txs.iter() {
-                    let (found, txi) = self.process_transaction(&block, tx).await?;
-                    if !found {
-                        continue;
+                // fetch batches ahead concurrently, `buffered` still yields blocks in order
+                let last_block = block_number.min(self.filter.end_block.unwrap_or(u64::MAX));
+                let batch_size = self.batch_size as u64;
+                let mut batches =
+                    stream::iter((block_to_process..=last_block).step_by(self.batch_size))
+                        .map(|x| self.fetch_blocks(x, last_block.min(x + batch_size - 1)))
+                        .buffered(self.concurrency);
+                while let Some(batch) = batches.next().await {
+                    for fetched in batch?.into_iter() {
+                        if let None = fetched {
+                            warn!("Block {} not found, wait for it", block_to_process);
+                            break 'fetching;
+                        }
+                        // stop between blocks, the cursor is at the end of the last one
+                        if self.is_shutting_down() {
+                            break 'stop_indexing;
+                        }
+                        let fetched = fetched.unwrap();
+                        if block_to_process > 0 {
+                            let parent_hash = self.get_block_hash(block_to_process - 1).await?;
+                            if parent_hash.is_some_and(|x| x.ne(&fetched.block.parent_hash)) {
+                                let ancestor =
+                                    self.find_common_ancestor(block_to_process - 1).await?;
+                                self.rollback(ancestor, block_to_process).await?;
+                                (block_to_process, block_txi) = (ancestor + 1, -1);
+                                continue 'fetching;
+                            }
+                        }
+                        info!(
+                            "Process block {} on chain {}",
+                            block_to_process, self.chain_id
+                        );
+                        self.process_block(fetched, block_txi).await?;
+                        (block_to_process, block_txi) = (block_to_process + 1, -1);
                     }
-                    block_txi = txi.unwrap();
                 }
-                self.mark_block_and_txi(block_to_process, block_txi).await?;
-                (block_to_process, block_txi) = next_block(block_to_process, block_txi);
             }
         }
         Ok(())
     }
 
-    async fn process_transaction(
+    /// Applies the transactions of a block after `block_txi` in order.
+    /// All changes of the block are committed at once along with the cursor, which moves to
+    /// the next block, so the block is the unit of a crash and of a rollback.
+    pub async fn process_block(
+        &self,
+        fetched: FetchedBlock,
+        block_txi: i64,
+    ) -> Result<(), anyhow::Error> {
+        let block_to_process = fetched.block.number.unwrap().as_u64();
+        let mut txs = fetched.block.transactions.to_owned();
+        let block: Block<H256> = fetched.block.into();
+        txs = txs
+            .into_iter()
+            .filter(|tx| tx.transaction_index.unwrap().as_u64() as i64 > block_txi)
+            .collect::<Vec<Transaction>>();
+        txs.sort_by(|x, y| x.transaction_index.cmp(&y.transaction_index));
+        let candidates = txs
+            .iter()
+            .filter(|tx| self.is_inscription_candidate(tx))
+            .map(|tx| tx.hash)
+            .collect();
+        let mut receipts = BlockReceipts::new(block_to_process, candidates, fetched.statuses);
+        // receipts are fetched first, the database isn't locked during RPC
+        let mut successful_txs = vec![];
+        for tx in txs.into_iter() {
+            if self.check_receipts
+                && self.is_inscription_candidate(&tx)
+                && !self.is_successful(&mut receipts, &tx).await?
+            {
+                continue;
+            }
+            successful_txs.push(tx);
+        }
+        let db = self.db.lock().await;
+        let txn = Txn::new(&db);
+        let mut journal = Journal::new(block_to_process);
+        for tx in successful_txs.iter() {
+            self.process_transaction(&txn, &mut journal, &block, tx)?;
+            let events = fetched.logs.get(&tx.transaction_index.unwrap().as_u64());
+            if events.is_none() {
+                continue;
+            }
+            self.process_ethscription_events(&txn, &mut journal, &block, tx, events.unwrap())?;
+        }
+        self.persist_journal(&txn, journal)?;
+        self.mark_block(&txn, block_to_process, block.hash.unwrap())?;
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// Cheap check on calldata to avoid fetching receipts of blocks without inscriptions.
+    fn is_inscription_candidate(&self, tx: &Transaction) -> bool {
+        if tx.to.is_none() || tx.input.is_empty() {
+            return false;
+        }
+        let scheme = SCHEME_DATA.as_bytes();
+        let is_data_uri =
+            tx.input.len() >= scheme.len() && tx.input[..scheme.len()].eq_ignore_ascii_case(scheme);
+        match self.indexed_type {
+            IndexedType::Ethscription => is_data_uri || tx.input.len() % 32 == 0,
+            _ => is_data_uri,
+        }
+    }
+
+    fn process_transaction(
         &self,
+        txn: &Txn,
+        journal: &mut Journal,
         block: &Block<H256>,
         tx: &Transaction,
-    ) -> Result<(bool, Option<i64>), anyhow::Error> {
-        let invalid_inscription_tx = (false, None);
-        if tx.to.is_none() {
-            return Ok(invalid_inscription_tx);
+    ) -> Result<(), anyhow::Error> {
+        if self.indexed_type == IndexedType::Ethscription {
+            return self.process_ethscription(txn, journal, block, tx);
         }
-        if self.filter.is_self_transaction && tx.to.unwrap().ne(&tx.from) {
-            return Ok(invalid_inscription_tx);
+        if tx.to.is_none() {
+            return Ok(());
         }
         if self.filter.recipient.is_some() && tx.to.unwrap().ne(&self.filter.recipient.unwrap()) {
-            return Ok(invalid_inscription_tx);
+            return Ok(());
         }
-        let input = String::from_utf8(tx.input.to_vec());
-        if input.is_err() {
-            return Ok(invalid_inscription_tx);
+        let data_uri = DataUri::parse(&tx.input);
+        if data_uri.is_err() {
+            return Ok(());
         }
-        let input = input.unwrap();
-        if !input.starts_with(PREFIX_INSCRIPTION) {
-            return Ok(invalid_inscription_tx);
+        let data_uri = data_uri.unwrap();
+        if self
+            .indexed_type
+            .mediatype()
+            .is_some_and(|x| data_uri.mediatype.ne(x))
+        {
+            return Ok(());
+        }
+        let data = data_uri.text();
+        if data.is_err() {
+            return Ok(());
         }
-        let data = input.strip_prefix(PREFIX_INSCRIPTION).unwrap_or("{}");
-        let deserialized = serde_json::from_str::<serde_json::Value>(data);
+        let deserialized = serde_json::from_str::<serde_json::Value>(&data.unwrap());
         if deserialized.is_err() {
-            return Ok(invalid_inscription_tx);
+            return Ok(());
         }
         let deserialized = deserialized.unwrap();
         if !deserialized.is_object() {
-            return Ok(invalid_inscription_tx);
+            return Ok(());
         }
         if !deserialized.is_valid_inscription() {
-            return Ok(invalid_inscription_tx);
+            return Ok(());
+        }
+        let mut inscription: Inscription = serde_json::from_value(deserialized)?;
+        let rules = self.protocols.get(&inscription.p);
+        if rules.is_none() {
+            return Ok(());
+        }
+        let rules = rules.unwrap();
+        // keys and filters are built from the normalized `p` and tick
+        let p = rules.normalize_p(&inscription.p);
+        let tick = rules.normalize_tick(&inscription.tick);
+        if p.is_none() || tick.is_none() {
+            return Ok(());
+        }
+        inscription.p = p.unwrap();
+        inscription.tick = tick.unwrap();
+        // transfers are sent to the recipient, so only deploy & mint must be self-transactions
+        if self.filter.is_self_transaction
+            && rules.requires_self_transaction()
+            && inscription.op.ne(OP_TRANSFER)
+            && tx.to.unwrap().ne(&tx.from)
+        {
+            return Ok(());
         }
-        let inscription: Inscription = serde_json::from_value(deserialized)?;
         if self.filter.p.is_some() && self.filter.p.as_ref().unwrap().ne(&inscription.p) {
-            return Ok(invalid_inscription_tx);
+            return Ok(());
         }
         if self.filter.tick.is_some() && self.filter.tick.as_ref().unwrap().ne(&inscription.tick) {
-            return Ok(invalid_inscription_tx);
+            return Ok(());
         }
-        let (_, indexed_txi) = self.process_inscription(block, tx, &inscription).await?;
-        Ok((true, Some(indexed_txi)))
+        self.process_inscription(txn, journal, block, tx, &inscription)
     }
 
-    async fn process_inscription(
+    fn process_inscription(
         &self,
+        txn: &Txn,
+        journal: &mut Journal,
         block: &Block<H256>,
         tx: &Transaction,
         inp: &Inscription,
-    ) -> Result<(u64, i64), anyhow::Error> {
-        let op = inp.op.as_str();
-        let _ = match op {
-            OP_MINT => self.persist_mint(block, tx, inp).await?,
-            OP_DEPLOY => self.persist_deploy(block, tx, inp).await?,
-            _ => return Err(anyhow!("Invalid operations")),
-        };
-        let indexed_block = tx.block_number.unwrap().as_u64() as u64;
-        let indexed_txi = tx.transaction_index.unwrap().as_u64() as i64;
-        Ok((indexed_block, indexed_txi))
+    ) -> Result<(), anyhow::Error> {
+        match inp.op.as_str() {
+            OP_MINT => self.persist_mint(txn, journal, block, tx, inp),
+            OP_DEPLOY => self.persist_deploy(txn, journal, block, tx, inp),
+            OP_TRANSFER => self.persist_transfer(txn, journal, block, tx, inp),
+            _ => Err(anyhow!("Invalid operations")),
+        }
     }
 
-    async fn mark_block_and_txi(
+    /// Moves the cursor past a complete block, a restart then checks the next block against
+    /// the recorded hash of this one.
+    fn mark_block(
         &self,
+        txn: &Txn,
         block_to_process: u64,
-        block_txi: i64,
+        block_hash: H256,
     ) -> Result<(), anyhow::Error> {
-        let db = self.db.lock().await;
-        let txn = db.transaction();
-        self.persist_block(&txn, block_to_process, block_txi)?;
-        txn.commit()?;
+        self.persist_block(txn, block_to_process + 1, -1)?;
+        let block_hash_key = self.key_block_hash(block_to_process);
+        let block_hash_value: String = block_hash.encode_hex();
+        txn.put(block_hash_key.as_bytes(), block_hash_value.as_bytes())?;
+        self.prune_journal(txn, block_to_process)?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::{testing::*, Tick};
+    use super::*;
+    use tempfile::TempDir;
+
+    fn inscription(mediatype: &str, json: &str) -> String {
+        format!("data:{},{}", mediatype, json)
+    }
+
+    #[tokio::test]
+    async fn indexed_types_keep_their_own_ledger() {
+        let dir = TempDir::new().unwrap();
+        let db = open_db(dir.path());
+        let deploy = r#"{"p":"brc-20","op":"deploy","tick":"ordi","max":"100","lim":"100"}"#;
+        let minter = address(1);
+        for (indexed_type, mediatype, amt) in [
+            (IndexedType::TextPlain, "", "100"),
+            (IndexedType::ApplicationJson, "application/json", "40"),
+        ] {
+            let indexer = indexer(&db, indexed_type);
+            let mint = format!(
+                r#"{{"p":"brc-20","op":"mint","tick":"ordi","amt":"{}"}}"#,
+                amt
+            );
+            let deploy = inscription(mediatype, deploy);
+            let mint = inscription(mediatype, &mint);
+            let txs = vec![tx(1, 0, minter, minter, &deploy)];
+            indexer.process_block(block(1, txs), -1).await.unwrap();
+            let txs = vec![tx(2, 0, minter, minter, &mint)];
+            indexer.process_block(block(2, txs), -1).await.unwrap();
+        }
+        for (indexed_type, minted) in [
+            (IndexedType::TextPlain, "100"),
+            (IndexedType::ApplicationJson, "40"),
+        ] {
+            let indexer = indexer(&db, indexed_type);
+            let tick: Tick = get(&indexer, &indexer.key_tick_deploy("brc-20", "ordi"))
+                .await
+                .unwrap();
+            assert_eq!(tick.minted, minted);
+            assert_eq!(tick.holders, "1");
+            let db = indexer.db.lock().await;
+            let txn = Txn::new(&db);
+            let balance = indexer
+                .get_balance(&txn, "brc-20", "ordi", &format!("{:?}", minter))
+                .unwrap();
+            assert_eq!(balance.to_plain_string(), minted);
+        }
+    }
+}
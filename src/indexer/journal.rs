@@ -0,0 +1,53 @@
+use super::columns::Txn;
+use std::collections::HashSet;
+
+#[derive(Debug)]
+pub struct UndoEntry {
+    pub key: String,
+    /// The value before the block was applied, `None` if the key didn't exist.
+    pub value: Option<Vec<u8>>,
+}
+
+/// Records the previous value of every key written while applying a block,
+/// so that the changes of an orphaned block can be reverted.
+pub struct Journal {
+    pub block: u64,
+    pub entries: Vec<UndoEntry>,
+    keys: HashSet<String>,
+}
+
+impl Journal {
+    pub fn new(block: u64) -> Self {
+        Journal {
+            block,
+            entries: vec![],
+            keys: HashSet::new(),
+        }
+    }
+
+    pub fn put(&mut self, txn: &Txn, key: &str, value: &[u8]) -> Result<(), anyhow::Error> {
+        self.record(txn, key)?;
+        txn.put(key.as_bytes(), value)?;
+        Ok(())
+    }
+
+    pub fn delete(&mut self, txn: &Txn, key: &str) -> Result<(), anyhow::Error> {
+        self.record(txn, key)?;
+        txn.delete(key.as_bytes())?;
+        Ok(())
+    }
+
+    /// Only the first write of a key matters, it holds the value to restore.
+    fn record(&mut self, txn: &Txn, key: &str) -> Result<(), anyhow::Error> {
+        if self.keys.contains(key) {
+            return Ok(());
+        }
+        let value = txn.get(key.as_bytes())?;
+        self.keys.insert(key.to_owned());
+        self.entries.push(UndoEntry {
+            key: key.to_owned(),
+            value,
+        });
+        Ok(())
+    }
+}
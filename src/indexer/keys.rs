@@ -1,14 +1,34 @@
-use super::Indexer;
+use super::{DBInscription, IndexedType, Indexer};
+use crate::config::ChainId;
 
 pub const WILDCARD: &'static str = "*";
 pub trait Keys {
-    fn key_indexed_record(&self) -> String;
+    fn key_indexed_record(&self, indexed_type: IndexedType) -> String;
     fn key_tick_mint(&self, p: &str, tick: &str, holder: &str, hash: &str, ts: u64) -> String;
+    fn key_mint_by_tx(&self, hash: &str) -> String;
+    fn key_mints_by_block_prefix(&self, block: u64) -> String;
+    fn key_mints_by_owner_prefix(&self, owner: &str) -> String;
     fn key_tick_deploy(&self, p: &str, tick: &str) -> String;
+    fn key_tick_balance(&self, p: &str, tick: &str, holder: &str) -> String;
+    fn key_ethscription(&self, id: &str) -> String;
+    fn key_ethscription_content(&self, content_sha: &str) -> String;
+    fn key_ethscription_owner(&self, owner: &str, id: &str) -> String;
+    fn key_ethscription_transfer(
+        &self,
+        id: &str,
+        block: u64,
+        txi: u64,
+        log_index: Option<u64>,
+    ) -> String;
+    fn key_block_hash(&self, block: u64) -> String;
+    fn key_undo_prefix(&self, block: u64) -> String;
+    fn key_undo(&self, block: u64, seq: u64) -> String;
+    fn key_undo_seq(&self, block: u64) -> String;
+    fn key_snapshot_import(&self) -> String;
 }
 
 impl Keys for Indexer {
-    fn key_indexed_record(&self) -> String {
+    fn key_indexed_record(&self, indexed_type: IndexedType) -> String {
         let p = if self.filter.p.is_some() {
             self.filter.p.as_deref().unwrap()
         } else {
@@ -19,15 +39,134 @@ impl Keys for Indexer {
         } else {
             WILDCARD
         };
-        format!("indexed#{}#{}#{}", self.chain_id, p, tick)
+        match indexed_type {
+            // keep the original cursor key so existing databases resume where they stopped
+            IndexedType::TextPlain => format!("indexed#{}#{}#{}", self.chain_id, p, tick),
+            _ => format!(
+                "indexed#{}#{}#{}#{}",
+                self.chain_id,
+                p,
+                tick,
+                indexed_type.name()
+            ),
+        }
     }
+    // ledger keys are namespaced by the indexed type like the cursor, each mode applies
+    // its inscriptions to its own ticks in block order and rolls back only its own changes
     fn key_tick_mint(&self, p: &str, tick: &str, holder: &str, hash: &str, ts: u64) -> String {
         format!(
-            "mint#{}#{}#{}#{}#{}#{}",
-            self.chain_id, p, tick, holder, hash, ts
+            "mint#{}#{}#{}#{}#{}#{}#{}",
+            self.chain_id,
+            self.indexed_type.name(),
+            p,
+            tick,
+            holder,
+            hash,
+            ts
         )
     }
+    fn key_mint_by_tx(&self, hash: &str) -> String {
+        key_mint_by_tx(self.chain_id, hash)
+    }
+    fn key_mints_by_block_prefix(&self, block: u64) -> String {
+        format!("mintblock#{}#{:020}#", self.chain_id, block)
+    }
+    fn key_mints_by_owner_prefix(&self, owner: &str) -> String {
+        format!("mintowner#{}#{}#", self.chain_id, owner)
+    }
     fn key_tick_deploy(&self, p: &str, tick: &str) -> String {
-        format!("deploy#{}#{}#{}", self.chain_id, p, tick)
+        format!(
+            "deploy#{}#{}#{}#{}",
+            self.chain_id,
+            self.indexed_type.name(),
+            p,
+            tick
+        )
+    }
+    fn key_tick_balance(&self, p: &str, tick: &str, holder: &str) -> String {
+        format!(
+            "balance#{}#{}#{}#{}#{}",
+            self.chain_id,
+            self.indexed_type.name(),
+            p,
+            tick,
+            holder
+        )
+    }
+    fn key_ethscription(&self, id: &str) -> String {
+        format!("ethscription#{}#{}", self.chain_id, id)
+    }
+    fn key_ethscription_content(&self, content_sha: &str) -> String {
+        format!("content#{}#{}", self.chain_id, content_sha)
+    }
+    fn key_ethscription_owner(&self, owner: &str, id: &str) -> String {
+        format!("owner#{}#{}#{}", self.chain_id, owner, id)
+    }
+    fn key_ethscription_transfer(
+        &self,
+        id: &str,
+        block: u64,
+        txi: u64,
+        log_index: Option<u64>,
+    ) -> String {
+        // zero padded so that the history of an ethscription is iterated in order,
+        // a calldata transfer comes before the events emitted by the same transaction
+        let seq = log_index.map(|x| x + 1).unwrap_or(0);
+        format!(
+            "transfer#{}#{}#{:020}#{:010}#{:010}",
+            self.chain_id, id, block, txi, seq
+        )
+    }
+    fn key_block_hash(&self, block: u64) -> String {
+        key_block_hash(self.chain_id, self.indexed_type.name(), block)
     }
+    fn key_undo_prefix(&self, block: u64) -> String {
+        format!(
+            "undo#{}#{}#{:020}#",
+            self.chain_id,
+            self.indexed_type.name(),
+            block
+        )
+    }
+    fn key_undo(&self, block: u64, seq: u64) -> String {
+        format!("{}{:010}", self.key_undo_prefix(block), seq)
+    }
+    fn key_undo_seq(&self, block: u64) -> String {
+        format!(
+            "undoseq#{}#{}#{:020}",
+            self.chain_id,
+            self.indexed_type.name(),
+            block
+        )
+    }
+    fn key_snapshot_import(&self) -> String {
+        format!("importing#{}", self.chain_id)
+    }
+}
+
+// the secondary indexes of a mint point to its `key_tick_mint`, they don't need an indexer
+// so that the migration indexing existing mints builds the same keys. A transaction is
+// inscribed with a single mediatype, so the indexes are shared by the indexed types.
+
+pub fn key_mint_by_tx(chain_id: ChainId, hash: &str) -> String {
+    format!("minttx#{}#{}", chain_id, hash)
+}
+
+/// The secondary index keys of a mint, by tx hash, by block and by owner.
+/// Within a block mints are ordered by transaction index. Mints of earlier releases don't
+/// know theirs and are ordered by hash, they never share a block with those which do.
+pub fn keys_mint_index(chain_id: ChainId, mint: &DBInscription) -> [String; 3] {
+    let position = match mint.txi {
+        Some(txi) => format!("{:020}#{:010}", mint.block, txi),
+        None => format!("{:020}#{}", mint.block, mint.id),
+    };
+    [
+        key_mint_by_tx(chain_id, &mint.id),
+        format!("mintblock#{}#{}", chain_id, position),
+        format!("mintowner#{}#{}#{}", chain_id, mint.owner, position),
+    ]
+}
+
+pub fn key_block_hash(chain_id: ChainId, indexed_type: &str, block: u64) -> String {
+    format!("blockhash#{}#{}#{:020}", chain_id, indexed_type, block)
 }
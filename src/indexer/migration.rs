@@ -0,0 +1,630 @@
+use super::{
+    codec::{is_encoded, Codec},
+    columns::{
+        column_of, Columns, CF_BALANCES, CF_CURSORS, CF_MINTS, CF_MINT_INDEXES, CF_TICKS, CF_UNDO,
+        COLUMNS,
+    },
+    journal::UndoEntry,
+    keys::{key_block_hash, keys_mint_index},
+    Balance, DBInscription, Ethscription, EthscriptionTransfer, IndexedRecord, IndexedType, Tick,
+};
+use crate::config::ChainId;
+use anyhow::anyhow;
+use bigdecimal::{BigDecimal, Zero};
+use log::{info, warn};
+use rocksdb::{Direction, IteratorMode, ReadOptions, TransactionDB, DEFAULT_COLUMN_FAMILY_NAME};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+
+const SCHEMA_VERSION_KEY: &'static str = "schema_version";
+// records rewritten per commit, so that a large database isn't upgraded in one transaction
+const MIGRATION_CHUNK: usize = 10_000;
+
+/// A step upgrading the database from the previous schema version to `version`.
+/// Steps must be safe to re-run, an interrupted upgrade starts over from its first step.
+struct Migration {
+    version: u32,
+    name: &'static str,
+    run: fn(&TransactionDB) -> Result<(), anyhow::Error>,
+}
+
+const MIGRATIONS: [Migration; 8] = [
+    Migration {
+        version: 1,
+        name: "move records into their column families",
+        run: move_into_columns,
+    },
+    Migration {
+        version: 2,
+        name: "encode values in binary",
+        run: encode_values,
+    },
+    Migration {
+        version: 3,
+        name: "index mints by tx hash, block and owner",
+        run: index_mints,
+    },
+    Migration {
+        version: 4,
+        name: "backfill balances from mints",
+        run: backfill_balances,
+    },
+    Migration {
+        version: 5,
+        name: "count holders from balances",
+        run: count_holders,
+    },
+    Migration {
+        version: 6,
+        name: "move cursors past their complete block",
+        run: advance_cursors,
+    },
+    Migration {
+        version: 7,
+        name: "namespace ticks, mints and balances by indexed type",
+        run: namespace_ledger,
+    },
+    // mints now keep their transaction index, which existing mints can't recover, the new
+    // version only keeps releases which can't read it away from the database
+    Migration {
+        version: 8,
+        name: "keep the transaction index of mints",
+        run: |_| Ok(()),
+    },
+];
+
+pub const fn latest_version() -> u32 {
+    MIGRATIONS[MIGRATIONS.len() - 1].version
+}
+
+/// Brings the database up to the latest schema version, a new database goes through every step.
+pub fn migrate(db: &TransactionDB) -> Result<(), anyhow::Error> {
+    let current = schema_version(db)?;
+    if current > latest_version() {
+        return Err(anyhow!(
+            "Database schema version {} is newer than {} of this indexer",
+            current,
+            latest_version()
+        ));
+    }
+    for migration in MIGRATIONS.iter().filter(|x| x.version > current) {
+        info!(
+            "Migrate database to version {}: {}",
+            migration.version, migration.name
+        );
+        (migration.run)(db)?;
+        db.put(
+            SCHEMA_VERSION_KEY.as_bytes(),
+            migration.version.to_string().as_bytes(),
+        )?;
+    }
+    Ok(())
+}
+
+fn schema_version(db: &TransactionDB) -> Result<u32, anyhow::Error> {
+    match db.get(SCHEMA_VERSION_KEY.as_bytes())? {
+        Some(bs) => Ok(String::from_utf8(bs)?.parse::<u32>()?),
+        None => Ok(0),
+    }
+}
+
+/// Rewrites the records of a column family for which `f` returns a new record,
+/// the old record is deleted when the new one has another key or column family.
+fn rewrite(
+    db: &TransactionDB,
+    column: &str,
+    f: impl Fn(&[u8], &[u8]) -> Result<Option<(Vec<u8>, Vec<u8>)>, anyhow::Error>,
+) -> Result<(), anyhow::Error> {
+    let cf = db.cf_handle(column).unwrap();
+    let mut rewritten = 0;
+    let mut txn = db.transaction();
+    for item in db.full_iterator_cf(cf, IteratorMode::Start) {
+        let (key, value) = item?;
+        let record = f(&key, &value)?;
+        if record.is_none() {
+            continue;
+        }
+        let (new_key, new_value) = record.unwrap();
+        if new_key.ne(&key.to_vec()) || column_of(&new_key) != column {
+            txn.delete_cf(cf, &key)?;
+        }
+        txn.put_cf(db.column(&new_key), &new_key, &new_value)?;
+        rewritten += 1;
+        if rewritten % MIGRATION_CHUNK == 0 {
+            txn.commit()?;
+            txn = db.transaction();
+            info!("Rewrote {} records of {}", rewritten, column);
+        }
+    }
+    txn.commit()?;
+    if rewritten > 0 {
+        info!("Rewrote {} records of {}", rewritten, column);
+    }
+    Ok(())
+}
+
+/// Records of a database created before the column families existed are in the default one.
+fn move_into_columns(db: &TransactionDB) -> Result<(), anyhow::Error> {
+    rewrite(db, DEFAULT_COLUMN_FAMILY_NAME, |key, value| {
+        if column_of(key) == DEFAULT_COLUMN_FAMILY_NAME {
+            return Ok(None);
+        }
+        Ok(Some((key.to_vec(), value.to_vec())))
+    })
+}
+
+fn encode_values(db: &TransactionDB) -> Result<(), anyhow::Error> {
+    for column in COLUMNS {
+        rewrite(db, column, |key, value| {
+            Ok(encode_value(key, value)?.map(|x| (key.to_vec(), x)))
+        })?;
+    }
+    Ok(())
+}
+
+/// Mints of blocks still in the undo journal are indexed without an undo entry, a rollback
+/// of such a block leaves index entries to a removed mint, which lookups skip.
+fn index_mints(db: &TransactionDB) -> Result<(), anyhow::Error> {
+    let cf = db.cf_handle(CF_MINTS).unwrap();
+    let mut indexed = 0;
+    let mut txn = db.transaction();
+    for item in db.full_iterator_cf(cf, IteratorMode::Start) {
+        let (key, value) = item?;
+        let chain_id = String::from_utf8(key.to_vec())?
+            .split('#')
+            .nth(1)
+            .ok_or(anyhow!("Malformed mint key"))?
+            .parse::<ChainId>()?;
+        let mint = DBInscription::decode(&value)?;
+        for index_key in keys_mint_index(chain_id, &mint).iter() {
+            txn.put_cf(db.column(index_key.as_bytes()), index_key.as_bytes(), &key)?;
+        }
+        indexed += 1;
+        if indexed % MIGRATION_CHUNK == 0 {
+            txn.commit()?;
+            txn = db.transaction();
+            info!("Indexed {} mints", indexed);
+        }
+    }
+    txn.commit()?;
+    info!("Indexed {} mints", indexed);
+    Ok(())
+}
+
+/// Balances are only tracked since transfers are indexed, mints before that left their minters
+/// without a balance. Each tick is backfilled in one transaction along with the undo entries of
+/// the credited balances, so a rollback restores the backfilled amount too.
+fn backfill_balances(db: &TransactionDB) -> Result<(), anyhow::Error> {
+    let undo_cf = db.cf_handle(CF_UNDO).unwrap();
+    let mut undo = undo_entries(db)?;
+    let mut backfilled = 0;
+    for item in db.full_iterator_cf(db.cf_handle(CF_TICKS).unwrap(), IteratorMode::Start) {
+        let (tick_key, value) = item?;
+        let tick = Tick::decode(&value)?;
+        let credits = missing_balances(db, &tick_key, &tick)?;
+        if credits.is_empty() {
+            continue;
+        }
+        let txn = db.transaction();
+        let mut credited = HashMap::new();
+        for (holder, amount) in credits.into_iter() {
+            let key = format!("{}{}", tick_records_prefix("balance", &tick_key)?, holder);
+            let balance = credit(
+                db.get_cf(db.column(key.as_bytes()), &key)?,
+                &tick,
+                &holder,
+                &amount,
+            )?;
+            txn.put_cf(db.column(key.as_bytes()), &key, &balance)?;
+            credited.insert(key, (holder, amount));
+            backfilled += 1;
+        }
+        for (key, entries) in undo.iter_mut() {
+            let mut patched = false;
+            for entry in entries.iter_mut() {
+                if let Some((holder, amount)) = credited.get(&entry.key) {
+                    entry.value = Some(credit(entry.value.take(), &tick, holder, amount)?);
+                    patched = true;
+                }
+            }
+            if patched {
+                txn.put_cf(undo_cf, &key, &entries.encode())?;
+            }
+        }
+        txn.commit()?;
+    }
+    info!("Backfilled {} balances", backfilled);
+    Ok(())
+}
+
+/// Holders were only counted since balances are tracked, ticks deployed before that kept "0".
+/// The undo entries of a tick are shifted by the same difference, the holders they restore
+/// were miscounted alike.
+fn count_holders(db: &TransactionDB) -> Result<(), anyhow::Error> {
+    let undo_cf = db.cf_handle(CF_UNDO).unwrap();
+    let mut undo = undo_entries(db)?;
+    let mut recounted = 0;
+    for item in db.full_iterator_cf(db.cf_handle(CF_TICKS).unwrap(), IteratorMode::Start) {
+        let (key, value) = item?;
+        let mut tick = Tick::decode(&value)?;
+        let balances_prefix = tick_records_prefix("balance", &key)?;
+        let mut holders: i64 = 0;
+        for (_, value) in scan_prefix(db, CF_BALANCES, &balances_prefix)?.iter() {
+            if !Balance::decode(value)?
+                .amount
+                .parse::<BigDecimal>()?
+                .is_zero()
+            {
+                holders += 1;
+            }
+        }
+        let delta = holders - tick.holders.parse::<i64>().unwrap_or(0);
+        if delta == 0 {
+            continue;
+        }
+        let txn = db.transaction();
+        tick.holders = holders.to_string();
+        txn.put_cf(db.column(&key), &key, &tick.encode())?;
+        let tick_key = String::from_utf8(key.to_vec())?;
+        for (key, entries) in undo.iter_mut() {
+            let mut patched = false;
+            for entry in entries.iter_mut().filter(|x| x.key.eq(&tick_key)) {
+                if let Some(value) = entry.value.as_ref() {
+                    let mut previous = Tick::decode(value)?;
+                    let holders = previous.holders.parse::<i64>().unwrap_or(0) + delta;
+                    previous.holders = holders.max(0).to_string();
+                    entry.value = Some(previous.encode());
+                    patched = true;
+                }
+            }
+            if patched {
+                txn.put_cf(undo_cf, &key, &entries.encode())?;
+            }
+        }
+        txn.commit()?;
+        recounted += 1;
+    }
+    info!("Recounted holders of {} ticks", recounted);
+    Ok(())
+}
+
+/// Cursors used to stay on the last indexed block, which a restart re-fetched without checking
+/// its hash. The hash of a block is recorded when the block is complete, so such a cursor moves
+/// to the next block, whose parent hash is checked.
+fn advance_cursors(db: &TransactionDB) -> Result<(), anyhow::Error> {
+    rewrite(db, CF_CURSORS, |key, value| {
+        let key_str = String::from_utf8(key.to_vec())?;
+        let segments = key_str.split('#').collect::<Vec<&str>>();
+        let chain_id = segments
+            .get(1)
+            .ok_or(anyhow!("Malformed cursor key {}", key_str))?
+            .parse::<ChainId>()?;
+        // the cursor of text/plain has no type segment
+        let indexed_type = segments
+            .get(4)
+            .copied()
+            .unwrap_or(IndexedType::TextPlain.name());
+        let mut record = IndexedRecord::decode(value)?;
+        let hash_key = key_block_hash(chain_id, indexed_type, record.indexed_block);
+        if db
+            .get_cf(db.column(hash_key.as_bytes()), hash_key.as_bytes())?
+            .is_none()
+        {
+            return Ok(None);
+        }
+        record.indexed_block += 1;
+        record.indexed_txi = -1;
+        Ok(Some((key.to_vec(), record.encode())))
+    })
+}
+
+/// Ledger keys used to be shared by text/plain and application/json, so both modes applied their
+/// inscriptions to the same ticks. The existing ledger is kept by text/plain, whose cursor
+/// predates the other modes, along with the mint indexes and undo entries pointing to it.
+fn namespace_ledger(db: &TransactionDB) -> Result<(), anyhow::Error> {
+    for column in [CF_TICKS, CF_MINTS, CF_BALANCES] {
+        rewrite(db, column, |key, value| {
+            Ok(namespace_key(key).map(|x| (x, value.to_vec())))
+        })?;
+    }
+    rewrite(db, CF_MINT_INDEXES, |key, value| {
+        Ok(namespace_key(value).map(|x| (key.to_vec(), x)))
+    })?;
+    rewrite(db, CF_UNDO, |key, value| {
+        if !key.starts_with(b"undo#") {
+            return Ok(None);
+        }
+        let mut entries = Vec::<UndoEntry>::decode(value)?;
+        let mut namespaced = false;
+        for entry in entries.iter_mut() {
+            if let Some(key) = namespace_key(entry.key.as_bytes()) {
+                entry.key = String::from_utf8(key)?;
+                namespaced = true;
+            }
+        }
+        if !namespaced {
+            return Ok(None);
+        }
+        Ok(Some((key.to_vec(), entries.encode())))
+    })
+}
+
+/// The text/plain key of a ledger key without a type segment, `None` for other keys.
+fn namespace_key(key: &[u8]) -> Option<Vec<u8>> {
+    let segments = key.splitn(3, |x| *x == b'#').collect::<Vec<&[u8]>>();
+    if segments.len() < 3 || !matches!(segments[0], b"deploy" | b"mint" | b"balance") {
+        return None;
+    }
+    // already namespaced by an interrupted run
+    let types = [
+        IndexedType::TextPlain,
+        IndexedType::ApplicationJson,
+        IndexedType::Ethscription,
+    ];
+    if types
+        .iter()
+        .any(|x| segments[2].starts_with(format!("{}#", x.name()).as_bytes()))
+    {
+        return None;
+    }
+    let mut namespaced = [segments[0], segments[1]].join(&b'#');
+    namespaced.push(b'#');
+    namespaced.extend_from_slice(IndexedType::TextPlain.name().as_bytes());
+    namespaced.push(b'#');
+    namespaced.extend_from_slice(segments[2]);
+    Some(namespaced)
+}
+
+/// The prefix shared by the `entity` records of the tick of `tick_key`, e.g. its balances,
+/// whichever the layout of the ledger keys.
+fn tick_records_prefix(entity: &str, tick_key: &[u8]) -> Result<String, anyhow::Error> {
+    let tick_key = String::from_utf8(tick_key.to_vec())?;
+    let scope = tick_key
+        .strip_prefix("deploy#")
+        .ok_or(anyhow!("Malformed tick key {}", tick_key))?;
+    Ok(format!("{}#{}#", entity, scope))
+}
+
+/// The undo records with their keys, `undoseq` counters are left out.
+fn undo_entries(db: &TransactionDB) -> Result<Vec<(Vec<u8>, Vec<UndoEntry>)>, anyhow::Error> {
+    let mut undo = vec![];
+    for item in db.full_iterator_cf(db.cf_handle(CF_UNDO).unwrap(), IteratorMode::Start) {
+        let (key, value) = item?;
+        if key.starts_with(b"undo#") {
+            undo.push((key.to_vec(), Vec::<UndoEntry>::decode(&value)?));
+        }
+    }
+    Ok(undo)
+}
+
+/// The mints of a tick its balances don't account for, summed up by minter.
+/// Balances sum up to the minted amount once every mint is credited, the missing amount is made
+/// of the mints of the first blocks, indexed before balances were tracked.
+fn missing_balances(
+    db: &TransactionDB,
+    tick_key: &[u8],
+    tick: &Tick,
+) -> Result<BTreeMap<String, BigDecimal>, anyhow::Error> {
+    let mut missing = tick.minted.parse::<BigDecimal>()?;
+    let balances_prefix = tick_records_prefix("balance", tick_key)?;
+    for (_, value) in scan_prefix(db, CF_BALANCES, &balances_prefix)?.iter() {
+        missing -= Balance::decode(value)?.amount.parse::<BigDecimal>()?;
+    }
+    let mut credits = BTreeMap::new();
+    if missing.le(&BigDecimal::zero()) {
+        return Ok(credits);
+    }
+    let mints_prefix = tick_records_prefix("mint", tick_key)?;
+    let mut mints = vec![];
+    for (_, value) in scan_prefix(db, CF_MINTS, &mints_prefix)?.iter() {
+        mints.push(DBInscription::decode(value)?);
+    }
+    mints.sort_by_key(|x| x.block);
+    let mut credited = BigDecimal::zero();
+    let mut last_block = None;
+    for mint in mints.iter() {
+        // the mints of a block were committed together, they are all credited or none
+        if credited.ge(&missing) && last_block.ne(&Some(mint.block)) {
+            break;
+        }
+        let amt = mint
+            .amt
+            .as_deref()
+            .unwrap_or_default()
+            .parse::<BigDecimal>()?;
+        credited += &amt;
+        *credits
+            .entry(mint.owner.to_owned())
+            .or_insert(BigDecimal::zero()) += amt;
+        last_block = Some(mint.block);
+    }
+    if credited.ne(&missing) {
+        warn!(
+            "Mints of {} on chain {} don't add up to the {} missing from its balances, skip it",
+            tick.tick, tick.chain_id, missing
+        );
+        credits.clear();
+    }
+    Ok(credits)
+}
+
+/// Adds `amount` to an encoded balance, a missing balance is created.
+fn credit(
+    value: Option<Vec<u8>>,
+    tick: &Tick,
+    holder: &str,
+    amount: &BigDecimal,
+) -> Result<Vec<u8>, anyhow::Error> {
+    let mut balance = match value {
+        Some(x) => Balance::decode(&x)?,
+        None => Balance {
+            chain_id: tick.chain_id,
+            p: tick.p.to_owned(),
+            tick: tick.tick.to_owned(),
+            holder: holder.to_owned(),
+            amount: "0".to_string(),
+        },
+    };
+    balance.amount = (balance.amount.parse::<BigDecimal>()? + amount).to_plain_string();
+    Ok(balance.encode())
+}
+
+/// Seeks in total order, a migration runs before the keys have the layout the prefix
+/// extractor of the column family expects.
+fn scan_prefix(
+    db: &TransactionDB,
+    column: &str,
+    prefix: &str,
+) -> Result<Vec<(Vec<u8>, Vec<u8>)>, anyhow::Error> {
+    let mut items = vec![];
+    let mut opts = ReadOptions::default();
+    opts.set_total_order_seek(true);
+    let mode = IteratorMode::From(prefix.as_bytes(), Direction::Forward);
+    for item in db.iterator_cf_opt(db.cf_handle(column).unwrap(), opts, mode) {
+        let (key, value) = item?;
+        if !key.starts_with(prefix.as_bytes()) {
+            break;
+        }
+        items.push((key.to_vec(), value.to_vec()));
+    }
+    Ok(items)
+}
+
+#[derive(Deserialize)]
+struct JsonUndoEntry {
+    key: String,
+    value: Option<String>,
+}
+
+/// The binary encoding of a JSON value, `None` if the value is already encoded
+/// or isn't a JSON struct.
+fn encode_value(key: &[u8], value: &[u8]) -> Result<Option<Vec<u8>>, anyhow::Error> {
+    if is_encoded(value) {
+        return Ok(None);
+    }
+    let entity = key.split(|x| *x == b'#').next().unwrap_or_default();
+    let encoded = match entity {
+        b"deploy" => serde_json::from_slice::<Tick>(value)?.encode(),
+        b"mint" => serde_json::from_slice::<DBInscription>(value)?.encode(),
+        b"balance" => serde_json::from_slice::<Balance>(value)?.encode(),
+        b"ethscription" => serde_json::from_slice::<Ethscription>(value)?.encode(),
+        b"transfer" => serde_json::from_slice::<EthscriptionTransfer>(value)?.encode(),
+        b"indexed" => serde_json::from_slice::<IndexedRecord>(value)?.encode(),
+        b"undo" => {
+            // the previous values are restored as is, so they are encoded too
+            let mut entries = vec![];
+            for entry in serde_json::from_slice::<Vec<JsonUndoEntry>>(value)? {
+                let value = match entry.value {
+                    Some(x) => Some(
+                        encode_value(entry.key.as_bytes(), x.as_bytes())?.unwrap_or(x.into_bytes()),
+                    ),
+                    None => None,
+                };
+                entries.push(UndoEntry {
+                    key: entry.key,
+                    value,
+                });
+            }
+            entries.encode()
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(encoded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::columns::{self, Txn};
+    use super::*;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    const HOLDER: &str = "0x0000000000000000000000000000000000001001";
+
+    fn mint_hash(block: u64) -> String {
+        format!("0x{:064x}", block)
+    }
+
+    /// The records of the first release: JSON values in the default column family, ticks
+    /// without decimals or holders and mints without balances.
+    fn write_baseline(path: &str) {
+        let db = TransactionDB::open_default(path).unwrap();
+        let tick = json!({
+            "id": mint_hash(1), "chain_id": 1, "chain": "Ethereum", "p": "brc-20",
+            "op": "deploy", "tick": "ordi", "max": "21000000", "lim": "1000",
+            "start_block": 1, "end_block": null, "minted": "2000", "mintable": true,
+            "holders": "0", "timestamp": 1_700_000_000u64, "deployer": HOLDER,
+        });
+        db.put("deploy#1#brc-20#ordi", tick.to_string()).unwrap();
+        for block in [2u64, 3] {
+            let mint = json!({
+                "id": mint_hash(block), "chain_id": 1, "chain": "Ethereum", "p": "brc-20",
+                "op": "mint", "tick": "ordi", "max": "21000000", "lim": "1000", "amt": "1000",
+                "block": block, "owner": HOLDER, "timestamp": 1_700_000_000 + block * 12,
+            });
+            let key = format!(
+                "mint#1#brc-20#ordi#{}#{}#{}",
+                HOLDER,
+                mint_hash(block),
+                1_700_000_000 + block * 12
+            );
+            db.put(key, mint.to_string()).unwrap();
+        }
+        let cursor = json!({"chain_id": 1, "indexed_block": 3, "indexed_txi": 0});
+        db.put("indexed#1#*#*", cursor.to_string()).unwrap();
+    }
+
+    #[test]
+    fn migrates_a_baseline_database() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().to_str().unwrap();
+        write_baseline(path);
+        let db = columns::open(path).unwrap();
+        assert_eq!(schema_version(&db).unwrap(), latest_version());
+        let default = db.cf_handle(DEFAULT_COLUMN_FAMILY_NAME).unwrap();
+        let leftovers = db
+            .full_iterator_cf(default, IteratorMode::Start)
+            .map(|x| String::from_utf8(x.unwrap().0.to_vec()).unwrap())
+            .filter(|x| x.ne(SCHEMA_VERSION_KEY))
+            .collect::<Vec<String>>();
+        assert!(leftovers.is_empty(), "{:?}", leftovers);
+
+        let txn = Txn::new(&db);
+        let get = |key: &str| txn.get(key.as_bytes()).unwrap();
+        assert!(get("deploy#1#brc-20#ordi").is_none());
+        let tick = Tick::decode(&get("deploy#1#text/plain#brc-20#ordi").unwrap()).unwrap();
+        assert_eq!(tick.minted, "2000");
+        assert_eq!(tick.dec, 18);
+        assert_eq!(tick.holders, "1");
+        let balance_key = format!("balance#1#text/plain#brc-20#ordi#{}", HOLDER);
+        let balance = Balance::decode(&get(&balance_key).unwrap()).unwrap();
+        assert_eq!(balance.amount, "2000");
+        let mint_key = get(&format!("minttx#1#{}", mint_hash(2))).unwrap();
+        let mint_key = String::from_utf8(mint_key).unwrap();
+        assert!(mint_key.starts_with("mint#1#text/plain#brc-20#ordi#"));
+        let mint = DBInscription::decode(&get(&mint_key).unwrap()).unwrap();
+        assert_eq!((mint.block, mint.amt.as_deref()), (2, Some("1000")));
+        // no block hash was recorded, so block 3 is re-fetched
+        let cursor = IndexedRecord::decode(&get("indexed#1#*#*").unwrap()).unwrap();
+        assert_eq!((cursor.indexed_block, cursor.indexed_txi), (3, 0));
+        drop(txn);
+
+        // a migrated database opens again at the same version
+        drop(db);
+        let db = columns::open(path).unwrap();
+        assert_eq!(schema_version(&db).unwrap(), latest_version());
+    }
+
+    #[test]
+    fn namespaces_ledger_keys_once() {
+        let key = b"balance#1#brc-20#ordi#0x01".to_vec();
+        let namespaced = namespace_key(&key).unwrap();
+        assert_eq!(
+            namespaced,
+            b"balance#1#text/plain#brc-20#ordi#0x01".to_vec()
+        );
+        assert_eq!(namespace_key(&namespaced), None);
+        assert_eq!(namespace_key(b"indexed#1#*#*"), None);
+        assert_eq!(namespace_key(b"deploy#1"), None);
+    }
+}
@@ -0,0 +1,121 @@
+use super::{codec::Codec, columns::Txn, keys::Keys, DBInscription, Indexer};
+use crate::utils::remove_leadering_zeros;
+use ethers::{
+    abi::AbiEncode,
+    types::{H160, H256},
+};
+
+impl Indexer {
+    /// The mint inscribed by a transaction.
+    pub async fn get_mint_by_tx(&self, hash: H256) -> Result<Option<DBInscription>, anyhow::Error> {
+        let db = self.db.lock().await;
+        let txn = Txn::new(&db);
+        let mint_key = txn.get(self.key_mint_by_tx(&hash.encode_hex()).as_bytes())?;
+        if mint_key.is_none() {
+            return Ok(None);
+        }
+        get_mint(&txn, &mint_key.unwrap())
+    }
+
+    /// The mints of a block in the order they were executed.
+    pub async fn get_mints_by_block(
+        &self,
+        block: u64,
+    ) -> Result<Vec<DBInscription>, anyhow::Error> {
+        let prefix = self.key_mints_by_block_prefix(block);
+        self.get_indexed_mints(&prefix).await
+    }
+
+    /// The mints of an owner across ticks in the order they were executed.
+    pub async fn get_mints_by_owner(
+        &self,
+        owner: H160,
+    ) -> Result<Vec<DBInscription>, anyhow::Error> {
+        let owner = remove_leadering_zeros(owner.encode_hex());
+        let prefix = self.key_mints_by_owner_prefix(&owner);
+        self.get_indexed_mints(&prefix).await
+    }
+
+    async fn get_indexed_mints(&self, prefix: &str) -> Result<Vec<DBInscription>, anyhow::Error> {
+        let db = self.db.lock().await;
+        let txn = Txn::new(&db);
+        let mut mints = vec![];
+        for (_, mint_key) in txn.scan_prefix(prefix)? {
+            if let Some(mint) = get_mint(&txn, &mint_key)? {
+                mints.push(mint);
+            }
+        }
+        Ok(mints)
+    }
+}
+
+// an index entry may outlive its mint when a block indexed by the migration is rolled back
+fn get_mint(txn: &Txn, mint_key: &[u8]) -> Result<Option<DBInscription>, anyhow::Error> {
+    match txn.get(mint_key)? {
+        Some(bs) => Ok(Some(DBInscription::decode(&bs)?)),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{testing::*, IndexedType};
+    use super::*;
+    use ethers::types::Transaction;
+    use tempfile::TempDir;
+
+    fn inscription(op: &str, tick: &str) -> String {
+        format!(
+            r#"data:,{{"p":"brc-20","op":"{}","tick":"{}","max":"100","lim":"10","amt":"1"}}"#,
+            op, tick
+        )
+    }
+
+    #[tokio::test]
+    async fn looks_up_mints_in_execution_order() {
+        let dir = TempDir::new().unwrap();
+        let indexer = indexer(&open_db(dir.path()), IndexedType::TextPlain);
+        let (a, b) = (address(1), address(2));
+        let txs = vec![
+            tx(1, 0, a, a, &inscription("deploy", "ordi")),
+            tx(1, 1, a, a, &inscription("deploy", "sats")),
+        ];
+        indexer.process_block(block(1, txs), -1).await.unwrap();
+        // the first mint of the block has the greatest hash
+        let first = Transaction {
+            hash: H256::repeat_byte(0xff),
+            ..tx(2, 0, b, b, &inscription("mint", "sats"))
+        };
+        let txs = vec![
+            first,
+            tx(2, 1, a, a, &inscription("mint", "ordi")),
+            tx(2, 2, b, b, &inscription("mint", "ordi")),
+        ];
+        indexer.process_block(block(2, txs), -1).await.unwrap();
+        let txs = vec![tx(3, 0, b, b, &inscription("mint", "ordi"))];
+        indexer.process_block(block(3, txs), -1).await.unwrap();
+
+        let mints = indexer.get_mints_by_block(2).await.unwrap();
+        let txis: Vec<_> = mints.iter().map(|x| x.txi).collect();
+        assert_eq!(txis, vec![Some(0), Some(1), Some(2)]);
+        assert_eq!(mints[0].id, H256::repeat_byte(0xff).encode_hex());
+        assert!(indexer.get_mints_by_block(1).await.unwrap().is_empty());
+
+        let mints = indexer.get_mints_by_owner(b).await.unwrap();
+        let positions: Vec<_> = mints.iter().map(|x| (x.block, x.tick.as_str())).collect();
+        assert_eq!(positions, vec![(2, "sats"), (2, "ordi"), (3, "ordi")]);
+        assert_eq!(indexer.get_mints_by_owner(a).await.unwrap().len(), 1);
+        assert!(indexer
+            .get_mints_by_owner(address(3))
+            .await
+            .unwrap()
+            .is_empty());
+
+        let hash = tx(3, 0, b, b, "").hash;
+        let mint = indexer.get_mint_by_tx(hash).await.unwrap().unwrap();
+        assert_eq!((mint.block, mint.txi), (3, Some(0)));
+        // a deploy is no mint
+        let hash = tx(1, 0, a, a, "").hash;
+        assert!(indexer.get_mint_by_tx(hash).await.unwrap().is_none());
+    }
+}
@@ -1,21 +1,40 @@
+pub mod codec;
+pub mod columns;
 pub mod database;
+pub mod datauri;
+pub mod ethscription;
+pub mod fetcher;
+pub mod heads;
 pub mod inscription;
+pub mod journal;
 pub mod keys;
+pub mod migration;
+pub mod mints;
+pub mod protocol;
+pub mod receipts;
+pub mod reorg;
+pub mod snapshot;
+pub mod source;
+#[cfg(test)]
+mod testing;
 
+use self::codec::Codec;
+use self::columns::Columns;
 use self::keys::Keys;
-use crate::config::{ChainId, CHAINS_CONFIG};
-use crate::config::{HttpProviders, Random};
-use ethers::providers::{Middleware, Provider};
+use self::protocol::ProtocolRegistry;
+use crate::config::{ChainId, HeadTag, CHAINS_CONFIG};
+use crate::pool::ProviderPool;
+use anyhow::anyhow;
+use ethers::providers::Middleware;
 use ethers::types::{BlockNumber, H160};
-use log::error;
-use rocksdb::{Options, TransactionDB, TransactionDBOptions, DB};
+use rocksdb::TransactionDB;
 use serde::{Deserialize, Serialize};
-use std::{process, sync::Arc};
-use tokio::sync::Mutex;
+use std::{str::FromStr, sync::atomic::AtomicBool, sync::Arc};
+use tokio::sync::{watch, Mutex};
 
 pub const OP_MINT: &'static str = "mint";
 pub const OP_DEPLOY: &'static str = "deploy";
-pub const PREFIX_INSCRIPTION: &'static str = "data:,";
+pub const OP_TRANSFER: &'static str = "transfer";
 pub const DEFAULT_DB_PATH: &'static str = "./data";
 pub const DEFAULT_START_TXI: i64 = -1;
 
@@ -24,6 +43,7 @@ lazy_static! {
         std::env::var("DB_PATH").unwrap_or(DEFAULT_DB_PATH.to_string());
 }
 
+#[derive(Clone)]
 pub struct Filter {
     pub is_self_transaction: bool,
     pub recipient: Option<H160>,
@@ -49,87 +69,159 @@ impl Filter {
 pub struct Indexer {
     chain_id: ChainId,
     indexed_type: IndexedType,
-    https: HttpProviders,
+    wss: String,
+    https: Arc<ProviderPool>,
     db: Arc<Mutex<TransactionDB>>,
     filter: Filter,
+    protocols: ProtocolRegistry,
+    check_receipts: bool,
+    block_receipts_unsupported: AtomicBool,
+    reorg_depth: u64,
+    confirmations: u64,
+    head: HeadTag,
+    concurrency: usize,
+    batch_size: usize,
+    shutdown: watch::Receiver<bool>,
+}
+
+/// Opens the database shared by the indexers of every chain, records are keyed by chain
+/// and stored in a column family per entity.
+pub fn open_db() -> Arc<Mutex<TransactionDB>> {
+    Arc::new(Mutex::new(columns::open(DB_PATH.as_str()).unwrap()))
 }
 
 impl Indexer {
-    pub async fn new(chain_id: ChainId, indexed_type: IndexedType, filter: Option<Filter>) -> Self {
+    /// Builds the providers of the chain without any request, ingesting a dump needs none.
+    pub fn new(
+        chain_id: ChainId,
+        indexed_type: IndexedType,
+        filter: Option<Filter>,
+        db: Arc<Mutex<TransactionDB>>,
+        shutdown: watch::Receiver<bool>,
+    ) -> Result<Self, anyhow::Error> {
         let config = CHAINS_CONFIG.get(&chain_id).unwrap();
-        let https = config
-            .https
-            .iter()
-            .map(|x| Arc::new(Provider::new_client(x, 5, 10).unwrap()))
-            .collect();
-        let mut opts = Options::default();
-        opts.create_if_missing(true);
-        let txn_opts = TransactionDBOptions::default();
-        let cfs: Vec<String> = DB::list_cf::<&str>(&opts, DB_PATH.as_str()).unwrap_or(vec![]);
-        let db = Arc::new(Mutex::new(
-            TransactionDB::open_cf(&opts, &txn_opts, DB_PATH.as_str(), cfs).unwrap(),
-        ));
+        let https = Arc::new(ProviderPool::new(&config.https)?);
+        let protocols = ProtocolRegistry::new(config.protocols.as_ref());
         let filter = if filter.is_some() {
             filter.unwrap()
         } else {
             Filter::default()
         };
-        Indexer {
+        Ok(Indexer {
             chain_id,
             indexed_type,
+            wss: config.wss.to_owned(),
             https,
             db,
             filter,
-        }
+            protocols,
+            check_receipts: config.check_receipts,
+            block_receipts_unsupported: AtomicBool::new(false),
+            reorg_depth: config.reorg_depth,
+            confirmations: config.confirmations,
+            head: config.head,
+            concurrency: config.concurrency.max(1),
+            batch_size: config.batch_size.max(1),
+            shutdown,
+        })
     }
-    pub async fn get_indexed_block(&self, indexed_type: IndexedType) -> (u64, i64) {
-        let indexed_key = self.key_indexed_record();
-        let indexed_value = self.db.lock().await.get(indexed_key.as_bytes());
-        if let Err(_) = indexed_value {
-            error!(
-                "Indexed block not found for {:?} {:?}",
-                self.chain_id, indexed_type
-            );
-            process::exit(1);
-        }
-        let indexed_value = indexed_value.unwrap();
+
+    pub fn is_shutting_down(&self) -> bool {
+        *self.shutdown.borrow()
+    }
+    pub async fn has_indexed_block(
+        &self,
+        indexed_type: IndexedType,
+    ) -> Result<bool, anyhow::Error> {
+        let indexed_key = self.key_indexed_record(indexed_type);
+        let db = self.db.lock().await;
+        self.check_snapshot_import(&db)?;
+        let indexed_value = db.get_cf(db.column(indexed_key.as_bytes()), indexed_key.as_bytes())?;
+        Ok(indexed_value.is_some())
+    }
+
+    /// The cursor, started at `START_BLOCK` or the head when nothing was indexed yet.
+    /// Errors are transient, e.g. an RPC outage while looking up the head, the caller retries.
+    pub async fn get_indexed_block(
+        &self,
+        indexed_type: IndexedType,
+    ) -> Result<(u64, i64), anyhow::Error> {
+        let indexed_key = self.key_indexed_record(indexed_type);
+        let db = self.db.lock().await;
+        self.check_snapshot_import(&db)?;
+        let indexed_value = db.get_cf(db.column(indexed_key.as_bytes()), indexed_key.as_bytes())?;
+        drop(db);
         if let Some(indexed_value) = indexed_value {
-            let indexed_record: IndexedRecord = serde_json::from_slice(&indexed_value).unwrap();
-            return (indexed_record.indexed_block, indexed_record.indexed_txi);
+            let indexed_record = IndexedRecord::decode(&indexed_value)?;
+            return Ok((indexed_record.indexed_block, indexed_record.indexed_txi));
         }
         let indexed_block = if self.filter.start_block.is_some() {
             self.filter.start_block.unwrap()
         } else {
+            let head = BlockNumber::from(self.head);
             self.https
-                .random()
-                .unwrap()
-                .get_block(BlockNumber::Latest)
-                .await
-                .unwrap()
-                .unwrap()
+                .call(0, |x| async move { x.provider.get_block(head).await })
+                .await?
+                .ok_or(anyhow!(
+                    "Block {:?} not found on chain {}",
+                    self.head,
+                    self.chain_id
+                ))?
                 .number
                 .unwrap()
                 .as_u64()
+                .saturating_sub(self.confirmations)
         };
         let indexed_record = IndexedRecord {
             chain_id: self.chain_id,
             indexed_block,
             indexed_txi: DEFAULT_START_TXI,
         };
-        let indexed_value = serde_json::to_string(&indexed_record).unwrap();
-        let _ = self
-            .db
-            .lock()
-            .await
-            .put(indexed_key.as_bytes(), indexed_value.as_bytes());
-        (indexed_record.indexed_block, indexed_record.indexed_txi)
+        let indexed_value = indexed_record.encode();
+        let db = self.db.lock().await;
+        db.put_cf(
+            db.column(indexed_key.as_bytes()),
+            indexed_key.as_bytes(),
+            &indexed_value,
+        )?;
+        Ok((indexed_record.indexed_block, indexed_record.indexed_txi))
     }
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum IndexedType {
     TextPlain,
     ApplicationJson,
+    Ethscription,
+}
+
+impl IndexedType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            IndexedType::TextPlain => "text/plain",
+            IndexedType::ApplicationJson => "application/json",
+            IndexedType::Ethscription => "ethscription",
+        }
+    }
+    /// The data URI mediatype indexed by this type, `None` accepts any mediatype.
+    pub fn mediatype(&self) -> Option<&'static str> {
+        match self {
+            IndexedType::Ethscription => None,
+            _ => Some(self.name()),
+        }
+    }
+}
+
+impl FromStr for IndexedType {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text/plain" | "TextPlain" => Ok(IndexedType::TextPlain),
+            "application/json" | "ApplicationJson" => Ok(IndexedType::ApplicationJson),
+            "ethscription" | "Ethscription" => Ok(IndexedType::Ethscription),
+            _ => Err(anyhow::anyhow!("Unsupported indexed type {}", s)),
+        }
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -147,6 +239,7 @@ pub struct Inscription {
     pub max: Option<String>,
     pub lim: Option<String>,
     pub amt: Option<String>,
+    pub dec: Option<String>,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -163,6 +256,9 @@ pub struct DBInscription {
     pub block: u64,
     pub owner: String,
     pub timestamp: u64,
+    /// The index of the transaction in its block, unknown for mints of earlier releases.
+    #[serde(default)]
+    pub txi: Option<u64>,
 }
 #[derive(Deserialize, Serialize, Debug)]
 pub struct Tick {
@@ -174,6 +270,8 @@ pub struct Tick {
     pub tick: String,
     pub max: Option<String>,
     pub lim: Option<String>,
+    #[serde(default = "default_decimals")]
+    pub dec: u32,
     pub start_block: u64,
     pub end_block: Option<u64>,
     pub minted: String,
@@ -182,6 +280,47 @@ pub struct Tick {
     pub timestamp: u64,
     pub deployer: String,
 }
+
+fn default_decimals() -> u32 {
+    protocol::MAX_DECIMALS
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct Balance {
+    pub chain_id: u64,
+    pub p: String,
+    pub tick: String,
+    pub holder: String,
+    pub amount: String,
+}
+#[derive(Deserialize, Serialize, Debug)]
+pub struct Ethscription {
+    pub id: String,
+    pub chain_id: u64,
+    pub chain: String,
+    pub content_sha: String,
+    pub mimetype: String,
+    pub creator: String,
+    pub initial_owner: String,
+    pub current_owner: String,
+    pub previous_owner: Option<String>,
+    pub block: u64,
+    pub txi: u64,
+    pub timestamp: u64,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct EthscriptionTransfer {
+    pub id: String,
+    pub from: String,
+    pub to: String,
+    pub hash: String,
+    pub block: u64,
+    pub txi: u64,
+    pub log_index: Option<u64>,
+    pub timestamp: u64,
+}
+
 trait InscriptionFieldValidate {
     fn is_valid_of(&self, field: &str) -> bool;
     fn is_valid_inscription(&self) -> bool;
@@ -201,8 +340,11 @@ impl InscriptionFieldValidate for serde_json::Value {
         }
         let op = self.get("op").unwrap().as_str().unwrap();
         match op {
-            OP_MINT => self.is_valid_of("amt"),
-            OP_DEPLOY => vec!["max", "lim"].iter().all(|x| self.is_valid_of(x)),
+            OP_MINT | OP_TRANSFER => self.is_valid_of("amt"),
+            OP_DEPLOY => {
+                vec!["max", "lim"].iter().all(|x| self.is_valid_of(x))
+                    && (self.get("dec").is_none() || self.is_valid_of("dec"))
+            }
             _ => false,
         }
     }
@@ -0,0 +1,197 @@
+use std::{collections::HashMap, sync::Arc};
+
+pub const MAX_DECIMALS: u32 = 18;
+
+/// Keys are `#` separated, a `p` or tick holding one would fall under the prefix of another tick.
+fn is_key_segment(s: &str) -> bool {
+    !s.is_empty() && !s.contains('#')
+}
+
+/// Semantics which differ between inscription protocols, keyed by the `p` field.
+pub trait ProtocolRules: Send + Sync {
+    fn name(&self) -> &'static str;
+    /// Returns `p` as stored in keys, the generic rules keep it as inscribed.
+    /// `None` if `p` can't be a key segment.
+    fn normalize_p(&self, p: &str) -> Option<String> {
+        if !is_key_segment(p) {
+            return None;
+        }
+        Some(p.to_owned())
+    }
+    /// Returns the tick as stored, or `None` if the tick is invalid for this protocol.
+    fn normalize_tick(&self, tick: &str) -> Option<String> {
+        if !is_key_segment(tick) {
+            return None;
+        }
+        Some(tick.to_owned())
+    }
+    /// Upper bound of the `dec` field of a deploy, also its default.
+    fn max_decimals(&self) -> u32 {
+        MAX_DECIMALS
+    }
+    /// Whether deploy & mint inscriptions must be sent to the sender itself.
+    fn requires_self_transaction(&self) -> bool {
+        true
+    }
+    /// Whether only the deployer of a tick is allowed to mint it.
+    fn requires_deployer_mint(&self) -> bool {
+        false
+    }
+}
+
+/// Accepts any `p` with the original semantics: case sensitive ticks of any length.
+pub struct Generic;
+
+impl ProtocolRules for Generic {
+    fn name(&self) -> &'static str {
+        "*"
+    }
+}
+
+/// Ticks are 4 characters and case insensitive.
+pub struct Brc20;
+
+impl ProtocolRules for Brc20 {
+    fn name(&self) -> &'static str {
+        "brc-20"
+    }
+    fn normalize_p(&self, _p: &str) -> Option<String> {
+        Some(self.name().to_owned())
+    }
+    fn normalize_tick(&self, tick: &str) -> Option<String> {
+        if tick.chars().count() != 4 || !is_key_segment(tick) {
+            return None;
+        }
+        Some(tick.to_lowercase())
+    }
+}
+
+/// The brc-20 forks of other chains, e.g. `asc-20` and `bsc-20`, named by their `p`.
+/// Ticks are case insensitive, up to 32 characters.
+pub struct Brc20Fork(pub &'static str);
+
+impl ProtocolRules for Brc20Fork {
+    fn name(&self) -> &'static str {
+        self.0
+    }
+    fn normalize_p(&self, _p: &str) -> Option<String> {
+        Some(self.name().to_owned())
+    }
+    fn normalize_tick(&self, tick: &str) -> Option<String> {
+        let len = tick.chars().count();
+        if len > 32 || !is_key_segment(tick) {
+            return None;
+        }
+        Some(tick.to_lowercase())
+    }
+}
+
+pub struct ProtocolRegistry {
+    protocols: HashMap<String, Arc<dyn ProtocolRules>>,
+    fallback: Option<Arc<dyn ProtocolRules>>,
+}
+
+impl ProtocolRegistry {
+    /// Registers the builtin protocols listed in `enabled`, `"*"` enables the generic rules
+    /// for any other `p`. Without a list every `p` follows the generic rules, so databases
+    /// indexed before protocols had their own rules keep their ticks.
+    pub fn new(enabled: Option<&Vec<String>>) -> Self {
+        let builtins: Vec<Arc<dyn ProtocolRules>> = vec![
+            Arc::new(Brc20),
+            Arc::new(Brc20Fork("asc-20")),
+            Arc::new(Brc20Fork("bsc-20")),
+        ];
+        let mut registry = ProtocolRegistry {
+            protocols: HashMap::new(),
+            fallback: None,
+        };
+        for rules in builtins.into_iter() {
+            if enabled.is_some_and(|x| x.iter().any(|x| x.eq(rules.name()))) {
+                registry.register(rules);
+            }
+        }
+        if enabled.is_none() || enabled.unwrap().iter().any(|x| x.eq(Generic.name())) {
+            registry.fallback = Some(Arc::new(Generic));
+        }
+        registry
+    }
+
+    pub fn register(&mut self, rules: Arc<dyn ProtocolRules>) {
+        self.protocols.insert(rules.name().to_owned(), rules);
+    }
+
+    pub fn get(&self, p: &str) -> Option<Arc<dyn ProtocolRules>> {
+        self.protocols
+            .get(&p.to_lowercase())
+            .or(self.fallback.as_ref())
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_key_separators() {
+        assert_eq!(Generic.normalize_p("a#b"), None);
+        assert_eq!(Generic.normalize_tick("a#b"), None);
+        assert_eq!(Generic.normalize_tick(""), None);
+        assert_eq!(Brc20.normalize_tick("a#bc"), None);
+        assert_eq!(Brc20Fork("asc-20").normalize_tick("ab#"), None);
+        assert_eq!(Generic.normalize_tick("a-b"), Some("a-b".to_string()));
+    }
+
+    fn enabled(enabled: &[&str]) -> ProtocolRegistry {
+        let enabled = enabled.iter().map(|x| x.to_string()).collect();
+        ProtocolRegistry::new(Some(&enabled))
+    }
+
+    #[test]
+    fn follows_the_generic_rules_by_default() {
+        let registry = ProtocolRegistry::new(None);
+        let rules = registry.get("brc-20").unwrap();
+        assert_eq!(rules.name(), "*");
+        assert_eq!(rules.normalize_p("BRC-20"), Some("BRC-20".to_string()));
+        assert_eq!(
+            rules.normalize_tick("Ordinals"),
+            Some("Ordinals".to_string())
+        );
+    }
+
+    #[test]
+    fn registers_the_enabled_protocols_only() {
+        let registry = enabled(&["brc-20"]);
+        assert_eq!(registry.get("brc-20").unwrap().name(), "brc-20");
+        assert!(registry.get("bsc-20").is_none());
+        assert!(registry.get("other").is_none());
+        let registry = enabled(&["bsc-20", "*"]);
+        assert_eq!(registry.get("bsc-20").unwrap().name(), "bsc-20");
+        assert_eq!(registry.get("brc-20").unwrap().name(), "*");
+        assert_eq!(registry.get("other").unwrap().name(), "*");
+    }
+
+    #[test]
+    fn matches_p_case_insensitively() {
+        let registry = enabled(&["brc-20", "asc-20"]);
+        let rules = registry.get("BRC-20").unwrap();
+        assert_eq!(rules.name(), "brc-20");
+        assert_eq!(rules.normalize_p("BRC-20"), Some("brc-20".to_string()));
+        let rules = registry.get("Asc-20").unwrap();
+        assert_eq!(rules.normalize_p("Asc-20"), Some("asc-20".to_string()));
+    }
+
+    #[test]
+    fn normalizes_ticks() {
+        let registry = enabled(&["brc-20", "bsc-20"]);
+        let brc20 = registry.get("brc-20").unwrap();
+        assert_eq!(brc20.normalize_tick("ORDI"), Some("ordi".to_string()));
+        assert_eq!(brc20.normalize_tick("ord"), None);
+        assert_eq!(brc20.normalize_tick("ordis"), None);
+        let bsc20 = registry.get("bsc-20").unwrap();
+        assert_eq!(bsc20.normalize_tick("BNBS"), Some("bnbs".to_string()));
+        let tick = "B".repeat(32);
+        assert_eq!(bsc20.normalize_tick(&tick), Some(tick.to_lowercase()));
+        assert_eq!(bsc20.normalize_tick(&"b".repeat(33)), None);
+    }
+}
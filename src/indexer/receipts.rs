@@ -0,0 +1,124 @@
+use super::Indexer;
+use crate::rpc::is_method_not_found;
+use ethers::{
+    providers::Middleware,
+    types::{Transaction, TransactionReceipt, H256},
+};
+use log::warn;
+use serde_json::json;
+use std::{collections::HashMap, sync::atomic::Ordering};
+
+/// Statuses of the transactions of a block, fetched on first use.
+pub struct BlockReceipts {
+    block: u64,
+    candidates: Vec<H256>,
+    statuses: Option<HashMap<H256, bool>>,
+}
+
+impl BlockReceipts {
+    /// `candidates` are the transactions whose receipts are batched
+    /// when the provider can't return the receipts of a whole block,
+    /// `statuses` are known up front when the block comes with its receipts.
+    pub fn new(block: u64, candidates: Vec<H256>, statuses: Option<HashMap<H256, bool>>) -> Self {
+        BlockReceipts {
+            block,
+            candidates,
+            statuses,
+        }
+    }
+}
+
+/// Receipts before byzantium carry no status, those transactions can't revert silently.
+pub fn is_success(receipt: &TransactionReceipt) -> bool {
+    receipt.status.map(|x| x.as_u64() == 1).unwrap_or(true)
+}
+
+impl Indexer {
+    /// Whether a transaction succeeded on-chain, reverted transactions are not inscriptions.
+    pub async fn is_successful(
+        &self,
+        receipts: &mut BlockReceipts,
+        tx: &Transaction,
+    ) -> Result<bool, anyhow::Error> {
+        if receipts.statuses.is_none() && !self.block_receipts_unsupported.load(Ordering::Relaxed) {
+            let block = receipts.block;
+            match self
+                .https
+                .call(block, |x| async move {
+                    x.provider.get_block_receipts(block).await
+                })
+                .await
+            {
+                Ok(block_receipts) => {
+                    let statuses = block_receipts
+                        .iter()
+                        .map(|x| (x.transaction_hash, is_success(x)))
+                        .collect();
+                    receipts.statuses = Some(statuses);
+                }
+                Err(e) if is_method_not_found(&e) => {
+                    warn!(
+                        "eth_getBlockReceipts is unsupported, fallback to eth_getTransactionReceipt: {}",
+                        e
+                    );
+                    self.block_receipts_unsupported
+                        .store(true, Ordering::Relaxed);
+                }
+                // a transient failure, only the receipts of this block are fetched one by one
+                Err(e) => warn!(
+                    "Failed to get receipts of block {}, fallback to eth_getTransactionReceipt: {}",
+                    block, e
+                ),
+            }
+        }
+        if receipts.statuses.is_none() && receipts.candidates.len() > 1 {
+            let candidates = &receipts.candidates;
+            let batch_receipts = self
+                .https
+                .call(receipts.block, |x| async move {
+                    if x.batch.is_unsupported() {
+                        return Ok(None);
+                    }
+                    let params = candidates.iter().map(|x| json!([x])).collect();
+                    let batch_receipts = x
+                        .batch
+                        .batch::<Option<TransactionReceipt>>("eth_getTransactionReceipt", params)
+                        .await;
+                    if batch_receipts.is_err() && x.batch.is_unsupported() {
+                        return Ok(None);
+                    }
+                    batch_receipts.map(Some)
+                })
+                .await;
+            match batch_receipts {
+                Ok(Some(batch_receipts)) => {
+                    let statuses = batch_receipts
+                        .iter()
+                        .flatten()
+                        .map(|x| (x.transaction_hash, is_success(x)))
+                        .collect();
+                    receipts.statuses = Some(statuses);
+                }
+                Ok(None) => receipts.statuses = Some(HashMap::new()),
+                Err(e) => {
+                    warn!("{}, fallback to single requests", e);
+                    receipts.statuses = Some(HashMap::new());
+                }
+            }
+        }
+        if let Some(status) = receipts.statuses.as_ref().and_then(|x| x.get(&tx.hash)) {
+            return Ok(*status);
+        }
+        let hash = tx.hash;
+        let receipt = self
+            .https
+            .call(receipts.block, |x| async move {
+                x.provider.get_transaction_receipt(hash).await
+            })
+            .await?;
+        if receipt.is_none() {
+            return Err(anyhow::anyhow!("Receipt not found for {:?}", tx.hash));
+        }
+        Ok(is_success(&receipt.unwrap()))
+    }
+}
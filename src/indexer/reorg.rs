@@ -0,0 +1,150 @@
+use super::{
+    codec::Codec,
+    columns::{Columns, Txn},
+    database::Persistable,
+    journal::UndoEntry,
+    keys::Keys,
+    Indexer,
+};
+use anyhow::{anyhow, Ok};
+use ethers::{providers::Middleware, types::H256};
+use log::warn;
+use std::str::FromStr;
+
+impl Indexer {
+    pub async fn get_block_hash(&self, block: u64) -> Result<Option<H256>, anyhow::Error> {
+        let block_hash_key = self.key_block_hash(block);
+        let db = self.db.lock().await;
+        let bs = db.get_cf(
+            db.column(block_hash_key.as_bytes()),
+            block_hash_key.as_bytes(),
+        )?;
+        if let None = bs {
+            return Ok(None);
+        }
+        let block_hash = String::from_utf8(bs.unwrap())?;
+        Ok(Some(H256::from_str(&block_hash)?))
+    }
+
+    /// Walks back from `block` until the recorded hash matches the canonical chain.
+    pub async fn find_common_ancestor(&self, block: u64) -> Result<u64, anyhow::Error> {
+        let mut ancestor = block;
+        loop {
+            let recorded = self.get_block_hash(ancestor).await?;
+            if recorded.is_none() {
+                return Err(anyhow!(
+                    "Reorg is deeper than the journal of {} blocks",
+                    self.reorg_depth
+                ));
+            }
+            let canonical = self
+                .https
+                .call(
+                    ancestor,
+                    |x| async move { x.provider.get_block(ancestor).await },
+                )
+                .await?
+                .and_then(|x| x.hash);
+            if canonical.eq(&recorded) {
+                return Ok(ancestor);
+            }
+            if ancestor == 0 {
+                return Err(anyhow!("No common ancestor found"));
+            }
+            ancestor -= 1;
+        }
+    }
+
+    /// Reverts every change made in the blocks after `ancestor` up to `tip`,
+    /// and moves the cursor to the block following `ancestor`.
+    pub async fn rollback(&self, ancestor: u64, tip: u64) -> Result<(), anyhow::Error> {
+        warn!(
+            "Chain reorganized, roll back blocks {} to {}",
+            ancestor + 1,
+            tip
+        );
+        let db = self.db.lock().await;
+        let txn = Txn::new(&db);
+        for block in (ancestor + 1..=tip).rev() {
+            self.revert_block(&txn, block)?;
+        }
+        self.persist_block(&txn, ancestor + 1, -1)?;
+        txn.commit()?;
+        Ok(())
+    }
+
+    fn revert_block(&self, txn: &Txn, block: u64) -> Result<(), anyhow::Error> {
+        let journals = txn.scan_prefix(&self.key_undo_prefix(block))?;
+        for (undo_key, undo_value) in journals.iter().rev() {
+            let entries = Vec::<UndoEntry>::decode(undo_value)?;
+            for entry in entries.iter().rev() {
+                match entry.value.as_ref() {
+                    Some(value) => txn.put(entry.key.as_bytes(), value)?,
+                    None => txn.delete(entry.key.as_bytes())?,
+                }
+            }
+            txn.delete(undo_key.as_bytes())?;
+        }
+        txn.delete(self.key_undo_seq(block).as_bytes())?;
+        txn.delete(self.key_block_hash(block).as_bytes())?;
+        Ok(())
+    }
+
+    /// Blocks deeper than the reorg depth are final, their journal is dropped.
+    pub fn prune_journal(&self, txn: &Txn, block: u64) -> Result<(), anyhow::Error> {
+        if block < self.reorg_depth {
+            return Ok(());
+        }
+        let pruned = block - self.reorg_depth;
+        for (undo_key, _) in txn.scan_prefix(&self.key_undo_prefix(pruned))?.iter() {
+            txn.delete(undo_key.as_bytes())?;
+        }
+        txn.delete(self.key_undo_seq(pruned).as_bytes())?;
+        txn.delete(self.key_block_hash(pruned).as_bytes())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{testing::*, IndexedType, Tick};
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn rollback_restores_the_state_before_the_block() {
+        let dir = TempDir::new().unwrap();
+        let indexer = indexer(&open_db(dir.path()), IndexedType::TextPlain);
+        let (a, b, c) = (address(1), address(2), address(3));
+        let deploy = r#"data:,{"p":"brc-20","op":"deploy","tick":"ordi","max":"100","lim":"10"}"#;
+        let mint = r#"data:,{"p":"brc-20","op":"mint","tick":"ordi","amt":"10"}"#;
+        let transfer = r#"data:,{"p":"brc-20","op":"transfer","tick":"ordi","amt":"10"}"#;
+        let txs = vec![tx(1, 0, a, a, deploy), tx(1, 1, a, a, mint)];
+        indexer.process_block(block(1, txs), -1).await.unwrap();
+        let before = dump(&indexer).await;
+
+        // a new holder mints, and the only holder so far sends its whole balance away
+        let txs = vec![tx(2, 0, b, b, mint), tx(2, 1, a, c, transfer)];
+        indexer.process_block(block(2, txs), -1).await.unwrap();
+        let tick_key = indexer.key_tick_deploy("brc-20", "ordi");
+        let tick: Tick = get(&indexer, &tick_key).await.unwrap();
+        assert_eq!((tick.minted.as_str(), tick.holders.as_str()), ("20", "2"));
+        assert_eq!(indexer.get_mints_by_block(2).await.unwrap().len(), 1);
+
+        indexer.rollback(1, 2).await.unwrap();
+        let tick: Tick = get(&indexer, &tick_key).await.unwrap();
+        assert_eq!((tick.minted.as_str(), tick.holders.as_str()), ("10", "1"));
+        assert!(indexer.get_mints_by_block(2).await.unwrap().is_empty());
+        assert!(indexer.get_mints_by_owner(b).await.unwrap().is_empty());
+        assert_eq!(indexer.get_mints_by_owner(a).await.unwrap().len(), 1);
+        assert_eq!(
+            indexer
+                .get_indexed_block(IndexedType::TextPlain)
+                .await
+                .unwrap(),
+            (2, -1)
+        );
+        // balances, mints, their indexes and the journal are all back as they were
+        assert_eq!(dump(&indexer).await, before);
+    }
+}
@@ -0,0 +1,408 @@
+use super::{
+    codec::Codec,
+    columns::{Columns, Txn, COLUMNS},
+    keys::Keys,
+    migration, IndexedRecord, IndexedType, Indexer,
+};
+use crate::config::ChainId;
+use anyhow::{anyhow, Ok};
+use ethers::{abi::AbiEncode, types::H256, utils::hex};
+use log::info;
+use rocksdb::{IteratorMode, TransactionDB};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+// records hold the values as stored, so the version follows the schema of the database
+pub const SNAPSHOT_VERSION: u32 = migration::latest_version();
+
+// records which only matter for rolling back recent blocks, not part of the state
+const JOURNAL_PREFIXES: [&str; 3] = ["undo#", "undoseq#", "blockhash#"];
+
+// records committed at once while importing, so that memory doesn't grow with the snapshot
+const IMPORT_CHUNK: u64 = 10_000;
+
+/// The last line of a snapshot, describing the records before it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotManifest {
+    pub version: u32,
+    pub chain_id: ChainId,
+    pub indexed_type: IndexedType,
+    /// The cursor, the next block to index.
+    pub block: u64,
+    pub txi: i64,
+    /// The hash of the last indexed block, the parent of `block`.
+    /// Both are also records, so they are covered by the state hash.
+    pub block_hash: String,
+    pub records: u64,
+    pub state_hash: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "lowercase")]
+enum SnapshotLine {
+    Record { key: String, value: String },
+    Manifest(SnapshotManifest),
+}
+
+/// Hash of the records in key order, each key and value is length-prefixed.
+#[derive(Default)]
+struct StateHasher {
+    hasher: Sha256,
+    records: u64,
+}
+
+impl StateHasher {
+    fn update(&mut self, key: &[u8], value: &[u8]) {
+        self.hasher.update((key.len() as u64).to_be_bytes());
+        self.hasher.update(key);
+        self.hasher.update((value.len() as u64).to_be_bytes());
+        self.hasher.update(value);
+        self.records += 1;
+    }
+
+    fn finalize(self) -> (u64, String) {
+        (
+            self.records,
+            format!("0x{}", hex::encode(self.hasher.finalize())),
+        )
+    }
+}
+
+impl Indexer {
+    /// Whether a record belongs to the state of this chain.
+    fn is_state_key(&self, key: &str) -> bool {
+        let chain = format!("#{}#", self.chain_id);
+        key.find('#').is_some_and(|x| key[x..].starts_with(&chain))
+            && !JOURNAL_PREFIXES.iter().any(|x| key.starts_with(x))
+    }
+
+    /// Writes every record of this chain along with the cursor and the hash of the state.
+    /// The indexer must not be running, so that the cursor block is complete.
+    pub async fn export_snapshot(&self, path: &Path) -> Result<SnapshotManifest, anyhow::Error> {
+        if !self.has_indexed_block(self.indexed_type).await? {
+            return Err(anyhow!("Chain {} is not indexed yet", self.chain_id));
+        }
+        let (block, txi) = self.get_indexed_block(self.indexed_type).await?;
+        let last_block = block
+            .checked_sub(1)
+            .ok_or(anyhow!("No block of chain {} is indexed", self.chain_id))?;
+        let block_hash = self
+            .get_block_hash(last_block)
+            .await?
+            .ok_or(anyhow!("Block hash of block {} not found", last_block))?;
+        let mut writer = BufWriter::new(File::create(path)?);
+        let mut hasher = StateHasher::default();
+        let db = self.db.lock().await;
+        // column families in a fixed order, each in key order
+        for name in COLUMNS {
+            let cf = db.cf_handle(name).unwrap();
+            for item in db.full_iterator_cf(cf, IteratorMode::Start) {
+                let (key, value) = item?;
+                let key = String::from_utf8(key.to_vec())?;
+                if !self.is_state_key(&key) {
+                    continue;
+                }
+                hasher.update(key.as_bytes(), &value);
+                let line = SnapshotLine::Record {
+                    key,
+                    value: hex::encode(value),
+                };
+                writeln!(writer, "{}", serde_json::to_string(&line)?)?;
+            }
+        }
+        drop(db);
+        // the hash of the last indexed block lets the cursor block be checked for a reorg
+        let block_hash_key = self.key_block_hash(last_block);
+        let block_hash_value: String = block_hash.encode_hex();
+        hasher.update(block_hash_key.as_bytes(), block_hash_value.as_bytes());
+        let line = SnapshotLine::Record {
+            key: block_hash_key,
+            value: hex::encode(block_hash_value),
+        };
+        writeln!(writer, "{}", serde_json::to_string(&line)?)?;
+        let (records, state_hash) = hasher.finalize();
+        let manifest = SnapshotManifest {
+            version: SNAPSHOT_VERSION,
+            chain_id: self.chain_id,
+            indexed_type: self.indexed_type,
+            block,
+            txi,
+            block_hash: block_hash.encode_hex(),
+            records,
+            state_hash,
+        };
+        let line = SnapshotLine::Manifest(manifest.to_owned());
+        writeln!(writer, "{}", serde_json::to_string(&line)?)?;
+        writer.flush()?;
+        info!(
+            "Exported {} records at block {} with state hash {}",
+            manifest.records, manifest.block, manifest.state_hash
+        );
+        Ok(manifest)
+    }
+
+    /// Loads a snapshot into an empty database, indexing then continues from its cursor.
+    /// The snapshot is rejected if its records don't match its state hash,
+    /// or the state hash doesn't match the `trusted_hash` when given.
+    pub async fn import_snapshot(
+        &self,
+        path: &Path,
+        trusted_hash: Option<&str>,
+    ) -> Result<SnapshotManifest, anyhow::Error> {
+        // the snapshot is verified before any record is written
+        let mut hasher = StateHasher::default();
+        let cursor_key = self.key_indexed_record(self.indexed_type);
+        let mut cursor = None;
+        let block_hash_prefix = self.key_block_hash(0);
+        let block_hash_prefix = &block_hash_prefix[..block_hash_prefix.rfind('#').unwrap() + 1];
+        let mut block_hash = None;
+        let manifest = read_snapshot(path, |key, value| {
+            let is_block_hash = key.starts_with(block_hash_prefix);
+            if !self.is_state_key(&key) && !(is_block_hash && block_hash.is_none()) {
+                return Err(anyhow!("Record {} doesn't belong to the chain", key));
+            }
+            hasher.update(key.as_bytes(), &value);
+            if is_block_hash {
+                block_hash = Some((key, String::from_utf8(value)?));
+            } else if key.eq(&cursor_key) {
+                cursor = Some(value);
+            }
+            Ok(())
+        })?;
+        if manifest.version != SNAPSHOT_VERSION {
+            return Err(anyhow!("Unsupported snapshot version {}", manifest.version));
+        }
+        if manifest.chain_id != self.chain_id || manifest.indexed_type != self.indexed_type {
+            return Err(anyhow!(
+                "Snapshot of chain {} {:?} can't be imported into chain {} {:?}",
+                manifest.chain_id,
+                manifest.indexed_type,
+                self.chain_id,
+                self.indexed_type
+            ));
+        }
+        let (records, state_hash) = hasher.finalize();
+        if records != manifest.records || state_hash.ne(&manifest.state_hash) {
+            return Err(anyhow!(
+                "Snapshot records don't match the state hash {}",
+                manifest.state_hash
+            ));
+        }
+        if trusted_hash.is_some_and(|x| !x.eq_ignore_ascii_case(&state_hash)) {
+            return Err(anyhow!(
+                "State hash {} is not the trusted one {}",
+                state_hash,
+                trusted_hash.unwrap()
+            ));
+        }
+        // the cursor and the block hash are records, the manifest must describe them
+        let last_block = manifest.block.checked_sub(1).ok_or(anyhow!(
+            "Snapshot of chain {} has no block",
+            manifest.chain_id
+        ))?;
+        let cursor_value = cursor.ok_or(anyhow!("Snapshot cursor not found"))?;
+        let cursor = IndexedRecord::decode(&cursor_value)?;
+        if cursor.indexed_block != manifest.block || cursor.indexed_txi != manifest.txi {
+            return Err(anyhow!(
+                "Snapshot cursor doesn't match block {} of its manifest",
+                manifest.block
+            ));
+        }
+        let manifest_hash: H256 = manifest.block_hash.parse()?;
+        let manifest_hash: String = manifest_hash.encode_hex();
+        if !block_hash.is_some_and(|(key, value)| {
+            key.eq(&self.key_block_hash(last_block)) && value.eq(&manifest_hash)
+        }) {
+            return Err(anyhow!(
+                "Snapshot block hash doesn't match {} of its manifest",
+                manifest.block_hash
+            ));
+        }
+
+        let db = self.db.lock().await;
+        let import_key = self.key_snapshot_import();
+        match db.get_cf(db.column(import_key.as_bytes()), import_key.as_bytes())? {
+            Some(x) if x.ne(state_hash.as_bytes()) => {
+                return Err(anyhow!(
+                    "Import of another snapshot of chain {} was interrupted",
+                    self.chain_id
+                ));
+            }
+            Some(_) => info!("Resume the interrupted import of {}", state_hash),
+            None => {
+                for name in COLUMNS {
+                    let cf = db.cf_handle(name).unwrap();
+                    for item in db.full_iterator_cf(cf, IteratorMode::Start) {
+                        let (key, _) = item?;
+                        if self.is_state_key(&String::from_utf8(key.to_vec())?) {
+                            return Err(anyhow!("Chain {} is already indexed", self.chain_id));
+                        }
+                    }
+                }
+            }
+        }
+        // records are committed in chunks while a marker of the import stands in for the
+        // cursor, which comes last, so an interrupted import is never taken for indexed state
+        let mut txn = Txn::new(&db);
+        txn.put(import_key.as_bytes(), state_hash.as_bytes())?;
+        let mut hasher = StateHasher::default();
+        read_snapshot(path, |key, value| {
+            hasher.update(key.as_bytes(), &value);
+            if key.eq(&cursor_key) {
+                return Ok(());
+            }
+            txn.put(key.as_bytes(), &value)?;
+            if hasher.records % IMPORT_CHUNK == 0 {
+                std::mem::replace(&mut txn, Txn::new(&db)).commit()?;
+                info!("Imported {} records", hasher.records);
+            }
+            Ok(())
+        })?;
+        // the snapshot may have changed since it was verified
+        if hasher.finalize().1.ne(&state_hash) {
+            txn.commit()?;
+            return Err(anyhow!("Snapshot changed while it was imported"));
+        }
+        txn.put(cursor_key.as_bytes(), &cursor_value)?;
+        txn.delete(import_key.as_bytes())?;
+        txn.commit()?;
+        info!(
+            "Imported {} records at block {} with state hash {}",
+            manifest.records, manifest.block, manifest.state_hash
+        );
+        Ok(manifest)
+    }
+
+    /// Refuses to index a chain whose snapshot import was interrupted, its records are partial.
+    pub fn check_snapshot_import(&self, db: &TransactionDB) -> Result<(), anyhow::Error> {
+        let import_key = self.key_snapshot_import();
+        if db
+            .get_cf(db.column(import_key.as_bytes()), import_key.as_bytes())?
+            .is_some()
+        {
+            return Err(anyhow!(
+                "Snapshot import of chain {} was interrupted, import it again",
+                self.chain_id
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Hands the records of a snapshot to `f` in order and returns its manifest.
+fn read_snapshot(
+    path: &Path,
+    mut f: impl FnMut(String, Vec<u8>) -> Result<(), anyhow::Error>,
+) -> Result<SnapshotManifest, anyhow::Error> {
+    let mut manifest = None;
+    for line in BufReader::new(File::open(path)?).lines() {
+        if manifest.is_some() {
+            return Err(anyhow!("Records found after the snapshot manifest"));
+        }
+        match serde_json::from_str::<SnapshotLine>(&line?)? {
+            SnapshotLine::Record { key, value } => f(key, hex::decode(value)?)?,
+            SnapshotLine::Manifest(x) => manifest = Some(x),
+        }
+    }
+    manifest.ok_or(anyhow!("Snapshot manifest not found, truncated?"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{testing::*, Tick};
+    use super::*;
+    use tempfile::TempDir;
+
+    /// A database of `CHAIN_ID` which indexed a deploy and a mint.
+    async fn indexed(dir: &Path) -> Indexer {
+        let indexer = indexer(&open_db(dir), IndexedType::TextPlain);
+        let minter = address(1);
+        let deploy = r#"data:,{"p":"brc-20","op":"deploy","tick":"ordi","max":"100","lim":"10"}"#;
+        let mint = r#"data:,{"p":"brc-20","op":"mint","tick":"ordi","amt":"10"}"#;
+        let txs = vec![tx(1, 0, minter, minter, deploy)];
+        indexer.process_block(block(1, txs), -1).await.unwrap();
+        let txs = vec![tx(2, 0, minter, minter, mint)];
+        indexer.process_block(block(2, txs), -1).await.unwrap();
+        indexer
+    }
+
+    #[tokio::test]
+    async fn imports_an_exported_snapshot() {
+        let dir = TempDir::new().unwrap();
+        let exporter = indexed(&dir.path().join("exporter")).await;
+        let path = dir.path().join("snapshot.jsonl");
+        let exported = exporter.export_snapshot(&path).await.unwrap();
+        let importer = indexer(
+            &open_db(&dir.path().join("importer")),
+            IndexedType::TextPlain,
+        );
+        let imported = importer.import_snapshot(&path, None).await.unwrap();
+        assert_eq!(imported, exported);
+        assert_eq!(
+            importer
+                .get_indexed_block(IndexedType::TextPlain)
+                .await
+                .unwrap(),
+            (3, -1)
+        );
+        let key = importer.key_tick_deploy("brc-20", "ordi");
+        let tick: Tick = get(&importer, &key).await.unwrap();
+        assert_eq!(tick.minted, "10");
+        // a database holding the chain doesn't take a snapshot
+        assert!(importer.import_snapshot(&path, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn resumes_an_interrupted_import_only() {
+        let dir = TempDir::new().unwrap();
+        let exporter = indexed(&dir.path().join("exporter")).await;
+        let path = dir.path().join("snapshot.jsonl");
+        let manifest = exporter.export_snapshot(&path).await.unwrap();
+        let importer = indexer(
+            &open_db(&dir.path().join("importer")),
+            IndexedType::TextPlain,
+        );
+        let import_key = importer.key_snapshot_import();
+        let tick_key = importer.key_tick_deploy("brc-20", "ordi");
+        // a chunk of the records was committed before the import stopped
+        let db = importer.db.lock().await;
+        let txn = Txn::new(&db);
+        txn.put(import_key.as_bytes(), b"0x00").unwrap();
+        let tick = raw(&exporter, &tick_key).await.unwrap();
+        txn.put(tick_key.as_bytes(), tick).unwrap();
+        txn.commit().unwrap();
+        drop(db);
+        assert!(importer
+            .has_indexed_block(IndexedType::TextPlain)
+            .await
+            .is_err());
+        assert!(importer
+            .get_indexed_block(IndexedType::TextPlain)
+            .await
+            .is_err());
+        // the records are of another snapshot
+        assert!(importer.import_snapshot(&path, None).await.is_err());
+        let db = importer.db.lock().await;
+        let txn = Txn::new(&db);
+        txn.put(import_key.as_bytes(), manifest.state_hash.as_bytes())
+            .unwrap();
+        txn.commit().unwrap();
+        drop(db);
+        importer.import_snapshot(&path, None).await.unwrap();
+        assert!(importer
+            .has_indexed_block(IndexedType::TextPlain)
+            .await
+            .unwrap());
+        assert!(raw(&importer, &import_key).await.is_none());
+    }
+
+    async fn raw(indexer: &Indexer, key: &str) -> Option<Vec<u8>> {
+        let db = indexer.db.lock().await;
+        Txn::new(&db).get(key.as_bytes()).unwrap()
+    }
+}
@@ -0,0 +1,196 @@
+use super::{
+    ethscription::group_ethscription_logs, fetcher::FetchedBlock, receipts::is_success,
+    IndexedType, Indexer,
+};
+use anyhow::{anyhow, Ok};
+use async_trait::async_trait;
+use ethers::types::{Block, Transaction, TransactionReceipt, H256};
+use flate2::read::MultiGzDecoder;
+use log::info;
+use serde_json::Value;
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{self, File},
+    io::{BufRead, BufReader, Lines},
+    path::{Path, PathBuf},
+};
+
+/// A source of blocks to ingest, yielding them in ascending order.
+#[async_trait]
+pub trait BlockSource: Send {
+    /// The next block, `None` once the source is exhausted.
+    async fn next_block(&mut self) -> Result<Option<FetchedBlock>, anyhow::Error>;
+}
+
+/// Blocks dumped as JSON lines, one `eth_getBlockByNumber(number, true)` result per line,
+/// optionally with the `eth_getBlockReceipts` result of the block under `receipts`.
+/// Files ending with `.gz` are decompressed.
+pub struct DumpFiles {
+    paths: VecDeque<PathBuf>,
+    lines: Option<Lines<Box<dyn BufRead + Send>>>,
+}
+
+impl DumpFiles {
+    /// `path` is either a dump file or a directory of dump files, read in the order of their names.
+    pub fn open(path: &Path) -> Result<Self, anyhow::Error> {
+        let mut paths = vec![];
+        if path.is_dir() {
+            for entry in fs::read_dir(path)? {
+                let entry = entry?.path();
+                if entry.is_file() {
+                    paths.push(entry);
+                }
+            }
+            paths.sort();
+        } else {
+            paths.push(path.to_path_buf());
+        }
+        Ok(DumpFiles {
+            paths: paths.into(),
+            lines: None,
+        })
+    }
+
+    fn next_line(&mut self) -> Result<Option<String>, anyhow::Error> {
+        loop {
+            if let Some(lines) = self.lines.as_mut() {
+                if let Some(line) = lines.next() {
+                    return Ok(Some(line?));
+                }
+            }
+            let path = self.paths.pop_front();
+            if path.is_none() {
+                return Ok(None);
+            }
+            let path = path.unwrap();
+            info!("Ingest blocks from {}", path.display());
+            let file = File::open(&path)?;
+            let reader: Box<dyn BufRead + Send> = if path.extension().is_some_and(|x| x.eq("gz")) {
+                Box::new(BufReader::new(MultiGzDecoder::new(file)))
+            } else {
+                Box::new(BufReader::new(file))
+            };
+            self.lines = Some(reader.lines());
+        }
+    }
+}
+
+#[async_trait]
+impl BlockSource for DumpFiles {
+    async fn next_block(&mut self) -> Result<Option<FetchedBlock>, anyhow::Error> {
+        loop {
+            let line = self.next_line()?;
+            if line.is_none() {
+                return Ok(None);
+            }
+            let line = line.unwrap();
+            if line.trim().is_empty() {
+                continue;
+            }
+            return Ok(Some(parse_dumped_block(&line)?));
+        }
+    }
+}
+
+fn parse_dumped_block(line: &str) -> Result<FetchedBlock, anyhow::Error> {
+    let mut value: Value = serde_json::from_str(line)?;
+    let receipts = value.as_object_mut().and_then(|x| x.remove("receipts"));
+    let block: Block<Transaction> = serde_json::from_value(value)?;
+    if block.number.is_none() || block.hash.is_none() {
+        return Err(anyhow!("Dumped block is pending"));
+    }
+    if receipts.is_none() {
+        return Ok(FetchedBlock {
+            block,
+            logs: HashMap::new(),
+            statuses: None,
+        });
+    }
+    let receipts: Vec<TransactionReceipt> = serde_json::from_value(receipts.unwrap())?;
+    let statuses: HashMap<H256, bool> = receipts
+        .iter()
+        .map(|x| (x.transaction_hash, is_success(x)))
+        .collect();
+    let number = block.number.unwrap().as_u64();
+    if receipts
+        .iter()
+        .any(|x| x.block_hash.is_some_and(|x| block.hash.ne(&Some(x))))
+    {
+        return Err(anyhow!(
+            "Receipts of dumped block {} belong to another block",
+            number
+        ));
+    }
+    // a missing receipt would be fetched over RPC
+    if block
+        .transactions
+        .iter()
+        .any(|x| !statuses.contains_key(&x.hash))
+    {
+        return Err(anyhow!(
+            "Receipts of dumped block {} are incomplete",
+            number
+        ));
+    }
+    let logs = group_ethscription_logs(receipts.into_iter().flat_map(|x| x.logs).collect())
+        .remove(&number)
+        .unwrap_or_default();
+    Ok(FetchedBlock {
+        block,
+        logs,
+        statuses: Some(statuses),
+    })
+}
+
+impl Indexer {
+    /// Applies the blocks of a source from the cursor on, without any RPC. Blocks must come with
+    /// their receipts when reverted transactions are skipped or ethscription events are indexed.
+    /// The first block of the source is the start if nothing was indexed yet.
+    pub async fn ingest(&self, source: &mut dyn BlockSource) -> Result<(), anyhow::Error> {
+        let (mut block_to_process, mut block_txi) =
+            if self.has_indexed_block(self.indexed_type).await? {
+                let (block, txi) = self.get_indexed_block(self.indexed_type).await?;
+                (Some(block), txi)
+            } else {
+                (self.filter.start_block, -1)
+            };
+        while let Some(mut fetched) = source.next_block().await? {
+            if self.is_shutting_down() {
+                return Ok(());
+            }
+            let number = fetched.block.number.unwrap().as_u64();
+            if block_to_process.is_some_and(|x| number < x) {
+                continue;
+            }
+            if block_to_process.is_some_and(|x| number > x) {
+                return Err(anyhow!(
+                    "Block {} is missing from the source",
+                    block_to_process.unwrap()
+                ));
+            }
+            if number > 0 {
+                let parent_hash = self.get_block_hash(number - 1).await?;
+                if parent_hash.is_some_and(|x| x.ne(&fetched.block.parent_hash)) {
+                    return Err(anyhow!("Block {} doesn't extend the indexed chain", number));
+                }
+            }
+            if fetched.statuses.is_none()
+                && (self.check_receipts || self.indexed_type == IndexedType::Ethscription)
+            {
+                return Err(anyhow!(
+                    "Dumped block {} has no receipts, needed for reverted transactions and ethscription events",
+                    number
+                ));
+            }
+            // the events of a dump are only applied where a live run fetches them
+            if self.indexed_type != IndexedType::Ethscription {
+                fetched.logs.clear();
+            }
+            info!("Process block {} on chain {}", number, self.chain_id);
+            self.process_block(fetched, block_txi).await?;
+            (block_to_process, block_txi) = (Some(number + 1), -1);
+        }
+        info!("Source exhausted before block {:?}", block_to_process);
+        Ok(())
+    }
+}
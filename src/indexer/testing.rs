@@ -0,0 +1,86 @@
+//! Indexers on a temporary database and blocks to apply to them, for the tests of the indexer.
+
+use super::{
+    codec::Codec,
+    columns::{self, Txn, COLUMNS},
+    fetcher::FetchedBlock,
+    IndexedType, Indexer,
+};
+use crate::config::ChainId;
+use ethers::types::{Block, Transaction, H160, H256, U256, U64};
+use rocksdb::{IteratorMode, TransactionDB};
+use std::{collections::HashMap, path::Path, sync::Arc};
+use tokio::sync::{watch, Mutex};
+
+pub const CHAIN_ID: ChainId = 1;
+
+pub fn open_db(path: &Path) -> Arc<Mutex<TransactionDB>> {
+    Arc::new(Mutex::new(columns::open(path.to_str().unwrap()).unwrap()))
+}
+
+/// An indexer of `CHAIN_ID` which is never shut down.
+pub fn indexer(db: &Arc<Mutex<TransactionDB>>, indexed_type: IndexedType) -> Indexer {
+    let (_, shutdown) = watch::channel(false);
+    Indexer::new(CHAIN_ID, indexed_type, None, db.to_owned(), shutdown).unwrap()
+}
+
+pub fn address(i: u64) -> H160 {
+    H160::from_low_u64_be(0x1000 + i)
+}
+
+pub fn block_hash(number: u64) -> H256 {
+    H256::from_low_u64_be(0x100000 + number)
+}
+
+/// The `txi`th transaction of block `number`, sending `input` from `from` to `to`.
+pub fn tx(number: u64, txi: u64, from: H160, to: H160, input: &str) -> Transaction {
+    Transaction {
+        hash: H256::from_low_u64_be((number << 16) | txi),
+        block_hash: Some(block_hash(number)),
+        block_number: Some(U64::from(number)),
+        transaction_index: Some(U64::from(txi)),
+        from,
+        to: Some(to),
+        input: input.as_bytes().to_vec().into(),
+        chain_id: Some(U256::from(CHAIN_ID)),
+        ..Default::default()
+    }
+}
+
+/// Block `number` on top of block `number - 1`, every transaction succeeded.
+pub fn block(number: u64, txs: Vec<Transaction>) -> FetchedBlock {
+    let statuses = txs.iter().map(|x| (x.hash, true)).collect();
+    FetchedBlock {
+        block: Block {
+            hash: Some(block_hash(number)),
+            parent_hash: block_hash(number.saturating_sub(1)),
+            number: Some(U64::from(number)),
+            timestamp: U256::from(1_700_000_000 + number * 12),
+            transactions: txs,
+            ..Default::default()
+        },
+        logs: HashMap::new(),
+        statuses: Some(statuses),
+    }
+}
+
+/// The decoded value of `key`, `None` if the key doesn't exist.
+pub async fn get<T: Codec>(indexer: &Indexer, key: &str) -> Option<T> {
+    let db = indexer.db.lock().await;
+    let value = Txn::new(&db).get(key.as_bytes()).unwrap();
+    value.map(|x| T::decode(&x).unwrap())
+}
+
+/// Every record of the database by column family, in key order.
+pub async fn dump(indexer: &Indexer) -> Vec<(&'static str, Vec<u8>, Vec<u8>)> {
+    let db = indexer.db.lock().await;
+    let mut records = vec![];
+    for name in COLUMNS {
+        let cf = db.cf_handle(name).unwrap();
+        for item in db.full_iterator_cf(cf, IteratorMode::Start) {
+            let (key, value) = item.unwrap();
+            records.push((name, key.to_vec(), value.to_vec()));
+        }
+    }
+    records
+}
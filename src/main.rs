@@ -1,43 +1,210 @@
 pub mod config;
 pub mod indexer;
+pub mod pool;
+pub mod rpc;
 pub mod utils;
 
-use config::ChainId;
-use indexer::{Filter, IndexedType, Indexer};
+use config::{ChainId, CHAINS_CONFIG};
+use futures::future::join_all;
+use indexer::{source::DumpFiles, Filter, IndexedType, Indexer};
 use log::{error, info};
+use rocksdb::TransactionDB;
+use std::{path::Path, process, sync::Arc, time::Duration};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::{watch, Mutex},
+};
 
 #[macro_use]
 extern crate lazy_static;
 
+// wait before retrying a chain whose indexing failed, e.g. on an RPC outage
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
 #[tokio::main]
 async fn main() {
     dotenv::dotenv().ok();
     pretty_env_logger::init_timed();
-    let chain_id = std::env::var("CHAIN_ID")
+    // a comma separated list of chains, indexed concurrently
+    let chain_ids = std::env::var("CHAIN_ID")
         .expect("CHAIN_ID must be set")
-        .parse::<ChainId>()
+        .split(',')
+        .map(|x| x.trim().parse::<ChainId>().unwrap())
+        .collect::<Vec<ChainId>>();
+    for chain_id in chain_ids.iter() {
+        if !CHAINS_CONFIG.contains_key(chain_id) {
+            panic!("Chain {} is not configured in chains.config.yaml", chain_id);
+        }
+        // the hash of the last block is needed to check the next one
+        if CHAINS_CONFIG.get(chain_id).unwrap().reorg_depth == 0 {
+            panic!("reorg_depth of chain {} must be at least 1", chain_id);
+        }
+    }
+    // block numbers are chain specific
+    if chain_ids.len() > 1 && std::env::var("START_BLOCK").is_ok() {
+        panic!("START_BLOCK is ambiguous for several chains, set START_BLOCK_<chain_id> instead");
+    }
+    let indexed_type = std::env::var("INDEXED_TYPE")
+        .map(|x| x.parse::<IndexedType>().unwrap())
+        .unwrap_or(IndexedType::TextPlain);
+    let db = indexer::open_db();
+    let (shutdown_sender, shutdown) = watch::channel(false);
+    tokio::spawn(handle_signals(shutdown_sender));
+    if let Ok(path) = std::env::var("SNAPSHOT_EXPORT") {
+        if chain_ids.len() != 1 {
+            panic!("SNAPSHOT_EXPORT holds the state of a single chain");
+        }
+        let indexer = Indexer::new(
+            chain_ids[0],
+            indexed_type,
+            filter_of(chain_ids[0]),
+            db.to_owned(),
+            shutdown,
+        )
+        .unwrap();
+        if let Err(e) = indexer.export_snapshot(Path::new(&path)).await {
+            error!("Error: {}", e);
+        }
+        flush_db(&db).await;
+        return;
+    }
+    if let Ok(path) = std::env::var("SNAPSHOT_IMPORT") {
+        if chain_ids.len() != 1 {
+            panic!("SNAPSHOT_IMPORT holds the state of a single chain");
+        }
+        let trusted_hash = std::env::var("SNAPSHOT_HASH").ok();
+        let indexer = Indexer::new(
+            chain_ids[0],
+            indexed_type,
+            filter_of(chain_ids[0]),
+            db.to_owned(),
+            shutdown,
+        )
+        .unwrap();
+        if let Err(e) = indexer
+            .import_snapshot(Path::new(&path), trusted_hash.as_deref())
+            .await
+        {
+            error!("Error: {}", e);
+        }
+        flush_db(&db).await;
+        return;
+    }
+    // ingest a dump of blocks instead of following the chain
+    if let Ok(path) = std::env::var("DUMP_PATH") {
+        if chain_ids.len() != 1 {
+            panic!("DUMP_PATH holds the blocks of a single chain");
+        }
+        let indexer = Indexer::new(
+            chain_ids[0],
+            indexed_type,
+            filter_of(chain_ids[0]),
+            db.to_owned(),
+            shutdown,
+        )
         .unwrap();
-    let mut filter: Option<Filter> = None;
-    if let Ok(value) = std::env::var("START_BLOCK") {
-        let start_block = value.parse::<u64>().unwrap();
-        filter = Some(Filter {
-            is_self_transaction: true,
-            recipient: None,
-            start_block: Some(start_block),
-            end_block: None,
-            p: None,
-            tick: None,
-        });
-    }
-    let indexer = Indexer::new(chain_id, IndexedType::TextPlain, filter).await;
-    loop {
+        let mut source = DumpFiles::open(Path::new(&path)).unwrap();
+        if let Err(e) = indexer.ingest(&mut source).await {
+            error!("Error: {}", e);
+        }
+        flush_db(&db).await;
+        return;
+    }
+    let chains = chain_ids.iter().map(|chain_id| {
+        tokio::spawn(index_chain(
+            *chain_id,
+            indexed_type,
+            filter_of(*chain_id),
+            db.to_owned(),
+            shutdown.to_owned(),
+        ))
+    });
+    // a chain failing doesn't stop the others
+    for (chain_id, result) in chain_ids.iter().zip(join_all(chains).await) {
+        if let Err(e) = result {
+            error!("Indexer of chain {} stopped: {}", chain_id, e);
+        }
+    }
+    flush_db(&db).await;
+    info!("Indexers stopped");
+}
+
+/// Starts a chain which hasn't been indexed yet at `START_BLOCK_<chain_id>`,
+/// or at `START_BLOCK` when it is the only chain.
+fn filter_of(chain_id: ChainId) -> Option<Filter> {
+    let value = std::env::var(format!("START_BLOCK_{}", chain_id))
+        .or_else(|_| std::env::var("START_BLOCK"))
+        .ok()?;
+    Some(Filter {
+        start_block: Some(value.parse::<u64>().unwrap()),
+        ..Filter::default()
+    })
+}
+
+async fn index_chain(
+    chain_id: ChainId,
+    indexed_type: IndexedType,
+    filter: Option<Filter>,
+    db: Arc<Mutex<TransactionDB>>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let indexer = loop {
+        let indexer = Indexer::new(
+            chain_id,
+            indexed_type,
+            filter.to_owned(),
+            db.to_owned(),
+            shutdown.to_owned(),
+        );
+        match indexer {
+            Ok(indexer) => break indexer,
+            Err(e) => {
+                error!("Failed to start the indexer of chain {}: {}", chain_id, e);
+                tokio::select! {
+                    _ = tokio::time::sleep(RETRY_DELAY) => {}
+                    _ = shutdown.wait_for(|x| *x) => return,
+                }
+            }
+        }
+    };
+    while !indexer.is_shutting_down() {
         match indexer.index_inscriptions().await {
             Err(e) => {
-                error!("Error: {}", e)
+                error!("Error on chain {}: {}", chain_id, e);
+                tokio::select! {
+                    _ = tokio::time::sleep(RETRY_DELAY) => {}
+                    _ = shutdown.wait_for(|x| *x) => {}
+                }
             }
             Ok(_) => {
-                info!("Pending new block")
+                info!("Pending new block on chain {}", chain_id)
             }
         }
     }
 }
+
+/// On SIGTERM or SIGINT the indexers finish their current block and stop,
+/// a second signal exits right away.
+async fn handle_signals(shutdown: watch::Sender<bool>) {
+    let mut terminate = signal(SignalKind::terminate()).unwrap();
+    let mut interrupt = signal(SignalKind::interrupt()).unwrap();
+    tokio::select! {
+        _ = terminate.recv() => {}
+        _ = interrupt.recv() => {}
+    }
+    info!("Shutting down after the current blocks");
+    shutdown.send_replace(true);
+    tokio::select! {
+        _ = terminate.recv() => {}
+        _ = interrupt.recv() => {}
+    }
+    error!("Forced to exit");
+    process::exit(1);
+}
+
+/// Syncs the write-ahead log so that every committed block survives the exit.
+async fn flush_db(db: &Arc<Mutex<TransactionDB>>) {
+    if let Err(e) = indexer::columns::sync_wal(&*db.lock().await) {
+        error!("Failed to flush the database: {}", e);
+    }
+}
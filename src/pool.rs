@@ -0,0 +1,237 @@
+use crate::config::{HttpConfig, HttpProvider};
+use crate::rpc::{BatchClient, Throttle, Throttled};
+use anyhow::anyhow;
+use ethers::providers::{Http, HttpRateLimitRetryPolicy, Middleware, Provider, RetryClient};
+use futures::future::join_all;
+use log::{info, warn};
+use std::{
+    future::Future,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+// weight of the latest sample in the moving averages
+const SMOOTHING: f64 = 0.2;
+// consecutive failures before an endpoint is quarantined
+const QUARANTINE_AFTER: u32 = 3;
+const MIN_QUARANTINE: Duration = Duration::from_secs(5);
+const MAX_QUARANTINE: Duration = Duration::from_secs(300);
+
+#[derive(Default, Debug)]
+struct Health {
+    latency_ms: f64,
+    error_rate: f64,
+    head: u64,
+    failures: u32,
+    quarantined_until: Option<Instant>,
+}
+
+impl Health {
+    /// Lower is better, errors weigh much more than latency.
+    fn score(&self) -> f64 {
+        (self.latency_ms + 1.0) * (1.0 + 10.0 * self.error_rate)
+    }
+
+    fn is_quarantined(&self, now: Instant) -> bool {
+        self.quarantined_until.is_some_and(|x| x > now)
+    }
+
+    fn record_success(&mut self, latency: Duration) {
+        let latency_ms = latency.as_secs_f64() * 1000.0;
+        self.latency_ms = SMOOTHING * latency_ms + (1.0 - SMOOTHING) * self.latency_ms;
+        self.error_rate *= 1.0 - SMOOTHING;
+        self.failures = 0;
+        self.quarantined_until = None;
+    }
+
+    /// Returns the quarantine duration once the endpoint keeps failing.
+    fn record_failure(&mut self) -> Option<Duration> {
+        self.error_rate = SMOOTHING + (1.0 - SMOOTHING) * self.error_rate;
+        self.failures += 1;
+        if self.failures < QUARANTINE_AFTER {
+            return None;
+        }
+        let exponent = (self.failures - QUARANTINE_AFTER).min(10);
+        let quarantine = (MIN_QUARANTINE * 2u32.pow(exponent)).min(MAX_QUARANTINE);
+        self.quarantined_until = Some(Instant::now() + quarantine);
+        Some(quarantine)
+    }
+}
+
+/// A JSON-RPC endpoint of a chain along with its health.
+pub struct Endpoint {
+    pub url: String,
+    pub provider: HttpProvider,
+    pub batch: BatchClient,
+    health: Mutex<Health>,
+}
+
+impl Endpoint {
+    pub fn head(&self) -> u64 {
+        self.health.lock().unwrap().head
+    }
+
+    fn record(&self, latency: Duration, succeeded: bool) {
+        let mut health = self.health.lock().unwrap();
+        if succeeded {
+            health.record_success(latency);
+            return;
+        }
+        if let Some(quarantine) = health.record_failure() {
+            warn!(
+                "Provider {} failed {} times in a row, quarantined for {:?}",
+                self.url, health.failures, quarantine
+            );
+        }
+    }
+}
+
+/// The providers of a chain, requests go to the healthiest endpoints which have reached
+/// the requested block and fail over to the next ones.
+pub struct ProviderPool {
+    endpoints: Vec<Arc<Endpoint>>,
+}
+
+impl ProviderPool {
+    pub fn new(configs: &[HttpConfig]) -> Result<Self, anyhow::Error> {
+        let mut endpoints = vec![];
+        for config in configs.iter() {
+            // retries go through the throttle as well
+            let throttle = Arc::new(Throttle::new(config.rps, config.max_concurrency));
+            let client = RetryClient::new(
+                Throttled::new(Http::from_str(&config.url)?, throttle.to_owned()),
+                Box::new(HttpRateLimitRetryPolicy),
+                config.retries,
+                config.backoff_ms,
+            );
+            endpoints.push(Arc::new(Endpoint {
+                url: config.url.to_owned(),
+                provider: Arc::new(Provider::new(client)),
+                batch: BatchClient::new(&config.url, throttle)?,
+                health: Mutex::new(Health::default()),
+            }));
+        }
+        if endpoints.is_empty() {
+            return Err(anyhow!("No https provider configured"));
+        }
+        Ok(ProviderPool { endpoints })
+    }
+
+    /// The highest head among the endpoints which are not quarantined.
+    pub fn head(&self) -> u64 {
+        let now = Instant::now();
+        self.endpoints
+            .iter()
+            .filter(|x| !x.health.lock().unwrap().is_quarantined(now))
+            .map(|x| x.head())
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Fetches the head of every endpoint which is not quarantined, this also probes their health.
+    pub async fn refresh_heads(&self) {
+        let now = Instant::now();
+        let endpoints = self
+            .endpoints
+            .iter()
+            .filter(|x| !x.health.lock().unwrap().is_quarantined(now));
+        join_all(endpoints.map(|endpoint| async move {
+            let started = Instant::now();
+            let head = endpoint.provider.get_block_number().await;
+            endpoint.record(started.elapsed(), head.is_ok());
+            match head {
+                Ok(head) => endpoint.health.lock().unwrap().head = head.as_u64(),
+                Err(e) => warn!("Failed to get the head of {}: {}", endpoint.url, e),
+            }
+        }))
+        .await;
+    }
+
+    /// Endpoints whose head is at least `min_head`, from the healthiest.
+    /// Quarantined endpoints are only used when nothing else is left.
+    fn candidates(&self, min_head: u64) -> Vec<Arc<Endpoint>> {
+        let now = Instant::now();
+        let mut candidates = self
+            .endpoints
+            .iter()
+            .map(|x| {
+                let health = x.health.lock().unwrap();
+                (x, health.is_quarantined(now), health.score(), health.head)
+            })
+            .filter(|(_, _, _, head)| *head >= min_head)
+            .collect::<Vec<_>>();
+        candidates.sort_by(|x, y| x.1.cmp(&y.1).then(x.2.total_cmp(&y.2)));
+        candidates.into_iter().map(|x| x.0.to_owned()).collect()
+    }
+
+    /// The healthiest endpoint, for requests which aren't tied to a block.
+    pub fn best(&self) -> Result<Arc<Endpoint>, anyhow::Error> {
+        self.candidates(0)
+            .into_iter()
+            .next()
+            .ok_or(anyhow!("No https provider configured"))
+    }
+
+    /// Whether any endpoint has reached `block`.
+    pub fn has_block(&self, block: u64) -> bool {
+        !self.candidates(block).is_empty()
+    }
+
+    /// Calls the healthiest endpoint which has reached `min_head`, failing over to the next ones.
+    pub async fn call<T, E, F, Fut>(&self, min_head: u64, f: F) -> Result<T, anyhow::Error>
+    where
+        F: Fn(Arc<Endpoint>) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: Into<anyhow::Error>,
+    {
+        self.call_nth(0, min_head, f).await
+    }
+
+    /// Like `call`, but starts from the `n`-th healthy endpoint so that concurrent requests
+    /// are spread over the pool.
+    pub async fn call_nth<T, E, F, Fut>(
+        &self,
+        n: usize,
+        min_head: u64,
+        f: F,
+    ) -> Result<T, anyhow::Error>
+    where
+        F: Fn(Arc<Endpoint>) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: Into<anyhow::Error>,
+    {
+        let mut candidates = self.candidates(min_head);
+        if candidates.is_empty() {
+            return Err(anyhow!("No provider has reached block {}", min_head));
+        }
+        let now = Instant::now();
+        let healthy = candidates
+            .iter()
+            .filter(|x| !x.health.lock().unwrap().is_quarantined(now))
+            .count();
+        if healthy > 0 {
+            candidates[..healthy].rotate_left(n % healthy);
+        }
+        let mut last_error = None;
+        for endpoint in candidates.into_iter() {
+            let started = Instant::now();
+            match f(endpoint.to_owned()).await {
+                Ok(result) => {
+                    endpoint.record(started.elapsed(), true);
+                    return Ok(result);
+                }
+                Err(e) => {
+                    let e = e.into();
+                    endpoint.record(started.elapsed(), false);
+                    info!(
+                        "Request to {} failed, try the next provider: {}",
+                        endpoint.url, e
+                    );
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error.unwrap())
+    }
+}
@@ -0,0 +1,233 @@
+use anyhow::anyhow;
+use async_trait::async_trait;
+use ethers::providers::{JsonRpcClient, ProviderError, RpcError};
+use reqwest::{Client, StatusCode, Url};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{
+    sync::{Mutex, Semaphore, SemaphorePermit},
+    time::{sleep_until, Instant},
+};
+
+#[derive(Serialize, Debug)]
+struct Request<'a> {
+    jsonrpc: &'a str,
+    id: usize,
+    method: &'a str,
+    params: Value,
+}
+
+#[derive(Deserialize, Debug)]
+struct Response {
+    id: usize,
+    result: Option<Value>,
+    error: Option<Value>,
+}
+
+/// Client side limits of a provider, requests per second and requests in flight.
+#[derive(Debug)]
+pub struct Throttle {
+    interval: Option<Duration>,
+    next: Mutex<Instant>,
+    permits: Option<Semaphore>,
+}
+
+impl Throttle {
+    pub fn new(rps: Option<u32>, max_concurrency: Option<usize>) -> Self {
+        Throttle {
+            interval: rps.filter(|x| *x > 0).map(|x| Duration::from_secs(1) / x),
+            next: Mutex::new(Instant::now()),
+            permits: max_concurrency.filter(|x| *x > 0).map(Semaphore::new),
+        }
+    }
+
+    /// Waits until `cost` more requests can be sent,
+    /// the permit must be held while the request is in flight.
+    pub async fn acquire(&self, cost: u32) -> Option<SemaphorePermit<'_>> {
+        let permit = match self.permits.as_ref() {
+            Some(permits) => Some(permits.acquire().await.unwrap()),
+            None => None,
+        };
+        if let Some(interval) = self.interval {
+            let mut next = self.next.lock().await;
+            let start = (*next).max(Instant::now());
+            *next = start + interval * cost;
+            drop(next);
+            sleep_until(start).await;
+        }
+        permit
+    }
+}
+
+/// A transport whose requests are subject to a `Throttle`.
+#[derive(Debug)]
+pub struct Throttled<C> {
+    inner: C,
+    throttle: Arc<Throttle>,
+}
+
+impl<C> Throttled<C> {
+    pub fn new(inner: C, throttle: Arc<Throttle>) -> Self {
+        Throttled { inner, throttle }
+    }
+}
+
+#[async_trait]
+impl<C: JsonRpcClient> JsonRpcClient for Throttled<C> {
+    type Error = C::Error;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let _permit = self.throttle.acquire(1).await;
+        self.inner.request(method, params).await
+    }
+}
+
+/// Sends several JSON-RPC calls of the same method in a single HTTP request.
+pub struct BatchClient {
+    url: Url,
+    client: Client,
+    throttle: Arc<Throttle>,
+    unsupported: AtomicBool,
+}
+
+impl BatchClient {
+    pub fn new(url: &str, throttle: Arc<Throttle>) -> Result<Self, anyhow::Error> {
+        Ok(BatchClient {
+            url: Url::parse(url)?,
+            client: Client::new(),
+            throttle,
+            unsupported: AtomicBool::new(false),
+        })
+    }
+
+    /// Whether the provider has rejected a batch, callers should fall back to single calls.
+    pub fn is_unsupported(&self) -> bool {
+        self.unsupported.load(Ordering::Relaxed)
+    }
+
+    /// Calls `method` once per params, results are returned in the order of `params`.
+    pub async fn batch<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: Vec<Value>,
+    ) -> Result<Vec<T>, anyhow::Error> {
+        let requests = params
+            .into_iter()
+            .enumerate()
+            .map(|(id, params)| Request {
+                jsonrpc: "2.0",
+                id,
+                method,
+                params,
+            })
+            .collect::<Vec<Request>>();
+        let size = requests.len();
+        // providers count every call of a batch against their limits
+        let _permit = self.throttle.acquire(size as u32).await;
+        let response = self
+            .client
+            .post(self.url.to_owned())
+            .json(&requests)
+            .send()
+            .await?;
+        // a batch rejected as a bad or too large request is retried with single calls,
+        // rate limits and server errors are failures of the provider
+        let status = response.status();
+        if status.is_client_error() && status != StatusCode::TOO_MANY_REQUESTS {
+            self.unsupported.store(true, Ordering::Relaxed);
+            return Err(anyhow!(
+                "Batch requests are rejected with status {} by {}",
+                status,
+                self.url
+            ));
+        }
+        let body = response.error_for_status()?.json::<Value>().await?;
+        // providers without batch support answer with a single error object
+        let responses = serde_json::from_value::<Vec<Response>>(body);
+        if responses.is_err() {
+            self.unsupported.store(true, Ordering::Relaxed);
+            return Err(anyhow!("Batch requests are unsupported by {}", self.url));
+        }
+        let mut responses = responses.unwrap();
+        if responses.len() != size {
+            self.unsupported.store(true, Ordering::Relaxed);
+            return Err(anyhow!("Batch responses are incomplete from {}", self.url));
+        }
+        responses.sort_by_key(|x| x.id);
+        responses
+            .into_iter()
+            .map(|x| match x.error {
+                Some(error) => Err(anyhow!("{} failed: {}", method, error)),
+                None => Ok(serde_json::from_value(x.result.unwrap_or(Value::Null))?),
+            })
+            .collect()
+    }
+}
+
+/// Whether a provider rejected a call because it doesn't implement the method,
+/// as opposed to a transient failure worth retrying.
+pub fn is_method_not_found(e: &anyhow::Error) -> bool {
+    let response = e
+        .downcast_ref::<ProviderError>()
+        .and_then(|x| RpcError::as_error_response(x));
+    if response.is_none() {
+        return false;
+    }
+    let response = response.unwrap();
+    let message = response.message.to_lowercase();
+    // not every provider uses the JSON-RPC code, e.g. `the method ... does not exist/is not available`
+    response.code == -32601
+        || (message.contains("method")
+            && [
+                "not found",
+                "not supported",
+                "does not exist",
+                "not available",
+            ]
+            .iter()
+            .any(|x| message.contains(x)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::providers::{HttpClientError, JsonRpcError};
+
+    fn error_response(code: i64, message: &str) -> anyhow::Error {
+        let e = HttpClientError::JsonRpcError(JsonRpcError {
+            code,
+            message: message.to_string(),
+            data: None,
+        });
+        ProviderError::JsonRpcClientError(Box::new(e)).into()
+    }
+
+    #[test]
+    fn detects_method_not_found() {
+        assert!(is_method_not_found(&error_response(
+            -32601,
+            "Method not found"
+        )));
+        assert!(is_method_not_found(&error_response(
+            -32000,
+            "the method eth_getBlockReceipts does not exist/is not available"
+        )));
+        assert!(!is_method_not_found(&error_response(
+            -32005,
+            "limit exceeded"
+        )));
+        assert!(!is_method_not_found(&anyhow!("connection reset")));
+    }
+}
@@ -1,17 +1,77 @@
-use ethers::{
-    types::{H160, H256},
-    utils::hex::ToHex,
-};
+use bigdecimal::BigDecimal;
+use ethers::types::{H160, H256};
 use regex::Regex;
-use std::str::FromStr;
+
+lazy_static! {
+    static ref AMOUNT_PATTERN: Regex = Regex::new(r"^[0-9]+(\.[0-9]+)?$").unwrap();
+    static ref DECIMALS_PATTERN: Regex = Regex::new(r"^[0-9]+$").unwrap();
+}
 
 pub fn remove_leadering_zeros(hex: String) -> String {
     let pattern: Regex = Regex::new("^0x0{24}").unwrap();
     pattern.replace(hex.as_str(), "0x").to_string()
 }
 
+/// Parses a plain decimal amount with at most `dec` fractional digits,
+/// signs, exponents, whitespace and empty strings are rejected.
+pub fn parse_amount(amount: &str, dec: u32) -> Option<BigDecimal> {
+    if !AMOUNT_PATTERN.is_match(amount) {
+        return None;
+    }
+    let fraction = amount.split_once('.').map(|(_, x)| x.len()).unwrap_or(0);
+    if fraction > dec as usize {
+        return None;
+    }
+    amount.parse::<BigDecimal>().ok()
+}
+
+pub fn parse_decimals(dec: &str, max_decimals: u32) -> Option<u32> {
+    if !DECIMALS_PATTERN.is_match(dec) {
+        return None;
+    }
+    dec.parse::<u32>().ok().filter(|x| *x <= max_decimals)
+}
+
+/// The address in the low 20 bytes of an indexed topic.
 pub fn h256_to_h160(h256: H256) -> H160 {
-    let h256_hex = h256.encode_hex();
-    let h160_hex = remove_leadering_zeros(h256_hex);
-    H160::from_str(h160_hex.as_str()).unwrap()
+    H160::from(h256)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn amount(amount: &str, dec: u32) -> Option<String> {
+        parse_amount(amount, dec).map(|x| x.to_plain_string())
+    }
+
+    #[test]
+    fn parses_plain_amounts_only() {
+        assert_eq!(amount("1000", 18), Some("1000".to_string()));
+        assert_eq!(amount("0.5", 18), Some("0.5".to_string()));
+        for malformed in [
+            "1e3", "+1", "-1", " 1", "1 ", "", ".5", "5.", "1.2.3", "0x10",
+        ] {
+            assert_eq!(amount(malformed, 18), None, "{:?}", malformed);
+        }
+    }
+
+    #[test]
+    fn rejects_amounts_finer_than_the_decimals() {
+        assert_eq!(amount("1.25", 2), Some("1.25".to_string()));
+        assert_eq!(amount("1.250", 2), None);
+        assert_eq!(amount("1.5", 0), None);
+        assert_eq!(amount("15", 0), Some("15".to_string()));
+    }
+
+    #[test]
+    fn parses_decimals_up_to_the_maximum() {
+        assert_eq!(parse_decimals("0", 18), Some(0));
+        assert_eq!(parse_decimals("18", 18), Some(18));
+        assert_eq!(parse_decimals("19", 18), None);
+        assert_eq!(parse_decimals("99999999999", 18), None);
+        for malformed in ["", "+8", "-1", " 8", "8.0", "1e1"] {
+            assert_eq!(parse_decimals(malformed, 18), None, "{:?}", malformed);
+        }
+    }
 }
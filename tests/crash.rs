@@ -0,0 +1,180 @@
+//! Kills the indexer at random points while it ingests a dump and checks that restarting it
+//! until the dump is exhausted ends in the same state as an uninterrupted run.
+//! Ingestion commits blocks like the live loop, but the live loop itself, its shutdown on a
+//! signal and a crash of the host aren't covered, they need a chain to follow and a power cut.
+
+use ethers::types::{Block, Transaction, TransactionReceipt, H160, H256, U256, U64};
+use serde_json::{json, Value};
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    process::{Command, ExitStatus},
+    thread::sleep,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tempfile::TempDir;
+
+const CHAIN_ID: u64 = 1;
+const BLOCKS: u64 = 400;
+const HOLDERS: u64 = 5;
+const KILLS: usize = 20;
+
+fn address(i: u64) -> H160 {
+    H160::from_low_u64_be(0x1000 + i)
+}
+
+fn block_hash(number: u64) -> H256 {
+    H256::from_low_u64_be(0x100000 + number)
+}
+
+fn inscription_tx(number: u64, txi: u64, from: H160, to: H160, data: Value) -> Transaction {
+    Transaction {
+        hash: H256::from_low_u64_be((number << 16) | txi),
+        block_hash: Some(block_hash(number)),
+        block_number: Some(U64::from(number)),
+        transaction_index: Some(U64::from(txi)),
+        from,
+        to: Some(to),
+        input: format!("data:,{}", data).into_bytes().into(),
+        chain_id: Some(U256::from(CHAIN_ID)),
+        ..Default::default()
+    }
+}
+
+/// A deploy in the first block, then mints of every holder and transfers between them.
+fn dumped_block(number: u64) -> Value {
+    let mut txs = vec![];
+    if number == 1 {
+        let deployer = address(0);
+        let deploy = json!({"p": "brc-20", "op": "deploy", "tick": "kill", "max": "100000000", "lim": "1000"});
+        txs.push(inscription_tx(number, 0, deployer, deployer, deploy));
+    } else {
+        for i in 0..HOLDERS {
+            let minter = address((number + i) % HOLDERS);
+            let mint = json!({"p": "brc-20", "op": "mint", "tick": "kill", "amt": "10"});
+            txs.push(inscription_tx(number, i, minter, minter, mint));
+        }
+        let transfer = json!({"p": "brc-20", "op": "transfer", "tick": "kill", "amt": "7"});
+        let (from, to) = (address(number % HOLDERS), address((number + 1) % HOLDERS));
+        txs.push(inscription_tx(number, HOLDERS, from, to, transfer));
+    }
+    let receipts = txs
+        .iter()
+        .map(|tx| TransactionReceipt {
+            transaction_hash: tx.hash,
+            transaction_index: tx.transaction_index.unwrap(),
+            block_hash: tx.block_hash,
+            block_number: tx.block_number,
+            status: Some(U64::from(1)),
+            ..Default::default()
+        })
+        .collect::<Vec<TransactionReceipt>>();
+    let block = Block {
+        hash: Some(block_hash(number)),
+        parent_hash: block_hash(number - 1),
+        number: Some(U64::from(number)),
+        timestamp: U256::from(1_700_000_000 + number * 12),
+        transactions: txs,
+        ..Default::default()
+    };
+    let mut value = serde_json::to_value(block).unwrap();
+    value["receipts"] = serde_json::to_value(receipts).unwrap();
+    value
+}
+
+fn write_dump(path: &Path) {
+    let mut writer = BufWriter::new(File::create(path).unwrap());
+    for number in 1..=BLOCKS {
+        writeln!(writer, "{}", dumped_block(number)).unwrap();
+    }
+    writer.flush().unwrap();
+}
+
+fn indexer(db_path: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_inscription"));
+    command
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .env("CHAIN_ID", CHAIN_ID.to_string())
+        .env("DB_PATH", db_path)
+        .env("RUST_LOG", "error");
+    command
+}
+
+fn ingest(db_path: &Path, dump: &Path) -> ExitStatus {
+    indexer(db_path).env("DUMP_PATH", dump).status().unwrap()
+}
+
+/// The state hash of the manifest ending an exported snapshot.
+fn state_hash(db_path: &Path, snapshot: &Path) -> String {
+    let status = indexer(db_path)
+        .env("SNAPSHOT_EXPORT", snapshot)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let manifest = BufReader::new(File::open(snapshot).unwrap())
+        .lines()
+        .last()
+        .unwrap()
+        .unwrap();
+    let manifest: Value = serde_json::from_str(&manifest).unwrap();
+    assert_eq!(manifest["manifest"]["block"], json!(BLOCKS + 1));
+    manifest["manifest"]["state_hash"]
+        .as_str()
+        .unwrap()
+        .to_string()
+}
+
+/// xorshift, seeded by the clock so that every run kills at other points.
+struct Random(u64);
+
+impl Random {
+    fn below(&mut self, n: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % n.max(1)
+    }
+}
+
+#[test]
+fn resumes_after_being_killed() {
+    let dir = TempDir::new().unwrap();
+    let dump = dir.path().join("dump.jsonl");
+    write_dump(&dump);
+
+    let reference = dir.path().join("reference");
+    let started = Instant::now();
+    assert!(ingest(&reference, &dump).success());
+    let duration = started.elapsed().as_millis() as u64;
+    let expected = state_hash(&reference, &dir.path().join("reference.jsonl"));
+
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as u64;
+    println!("seed {}", seed);
+    let mut random = Random(seed | 1);
+    let crashed = dir.path().join("crashed");
+    let mut exhausted = false;
+    for _ in 0..KILLS {
+        let mut child = indexer(&crashed).env("DUMP_PATH", &dump).spawn().unwrap();
+        sleep(Duration::from_millis(random.below(duration)));
+        if let Some(status) = child.try_wait().unwrap() {
+            assert!(status.success());
+            exhausted = true;
+            break;
+        }
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+    if !exhausted {
+        assert!(ingest(&crashed, &dump).success());
+    }
+    let actual = state_hash(&crashed, &dir.path().join("crashed.jsonl"));
+    assert_eq!(
+        actual, expected,
+        "state differs after crashes, seed {}",
+        seed
+    );
+}